@@ -248,6 +248,12 @@ impl Scene {
     world.register::<Relationship>();
     world.register::<prefabs::GeomSphere>();
     world.register::<prefabs::Camera>();
+    world.register::<prefabs::Material>();
+    world.register::<prefabs::Medium>();
+    world.register::<prefabs::Volume>();
+    world.register::<prefabs::PointLight>();
+    world.register::<prefabs::SpotLight>();
+    world.register::<prefabs::DirectionalLight>();
     world.register::<gfx::Transform>();
     world.register::<gfx::Mesh>();
 
@@ -4,11 +4,14 @@ use specs_derive::Component;
 #[derive(Component, Default)]
 pub struct Transform {
   affine: glam::Affine3A,
+  /// Keyframe at the end of the frame, for motion blur. The transform is static when unset.
+  end: Option<glam::Affine3A>,
 }
 impl Transform {
   pub fn new() -> Self {
     Self {
       affine: glam::Affine3A::IDENTITY,
+      end: None,
     }
   }
   pub fn translation(&self) -> glam::Vec3A {
@@ -17,11 +20,13 @@ impl Transform {
   pub fn from_translation(translation: glam::Vec3) -> Self {
     Self {
       affine: glam::Affine3A::from_translation(translation),
+      end: None,
     }
   }
   pub fn from_translation_rotation(translation: glam::Vec3, rotation: glam::Quat) -> Self {
     Self {
       affine: glam::Affine3A::from_rotation_translation(rotation, translation),
+      end: None,
     }
   }
   pub fn from_translation_rotation_scale(
@@ -31,6 +36,7 @@ impl Transform {
   ) -> Self {
     Self {
       affine: glam::Affine3A::from_scale_rotation_translation(scale, rotation, translation),
+      end: None,
     }
   }
   pub fn look_at(eye: glam::Vec3, target: glam::Vec3, up: glam::Vec3) -> Self {
@@ -38,17 +44,29 @@ impl Transform {
     let ex = up.cross(ez).normalize();
     let ey = ez.cross(ex);
     let affine = glam::Affine3A::from_mat3_translation(glam::Mat3::from_cols(ex, ey, ez), eye);
-    Self { affine }
+    Self { affine, end: None }
+  }
+  /// Animate from this transform at the start of the frame to `end` at its end.
+  pub fn with_end_keyframe(mut self, end: glam::Affine3A) -> Self {
+    self.end = Some(end);
+    self
+  }
+  pub fn is_animated(&self) -> bool {
+    self.end.is_some()
   }
   pub fn affine(&self) -> &glam::Affine3A {
     &self.affine
   }
+  /// The transform at the end of the frame, which is the start transform when not animated.
+  pub fn end_affine(&self) -> &glam::Affine3A {
+    self.end.as_ref().unwrap_or(&self.affine)
+  }
   pub fn to_matrix(&self) -> glam::Mat4 {
     glam::Mat4::from(self.affine)
   }
 }
 impl From<glam::Affine3A> for Transform {
   fn from(affine: glam::Affine3A) -> Self {
-    Self { affine }
+    Self { affine, end: None }
   }
 }
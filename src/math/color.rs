@@ -21,6 +21,30 @@ impl Color {
   pub fn new(r: f32, g: f32, b: f32) -> Self {
     Self { r, g, b }
   }
+  pub fn splat(v: f32) -> Self {
+    Self { r: v, g: v, b: v }
+  }
+  pub fn max_component(&self) -> f32 {
+    self.r.max(self.g).max(self.b)
+  }
+  /// Relative luminance of linear Rec. 709 primaries.
+  pub fn luminance(&self) -> f32 {
+    0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+  }
+  /// Encode linear values with the sRGB transfer function (IEC 61966-2-1).
+  pub fn to_srgb(&self) -> Self {
+    fn oetf(v: f32) -> f32 {
+      if v <= 0.0031308 {
+        12.92 * v
+      } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+      }
+    }
+    Self::new(oetf(self.r), oetf(self.g), oetf(self.b))
+  }
+  pub fn is_black(&self) -> bool {
+    self.r == 0.0 && self.g == 0.0 && self.b == 0.0
+  }
 }
 
 impl Into<[u8; 4]> for Color {
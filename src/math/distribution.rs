@@ -0,0 +1,108 @@
+/// Piecewise-constant 1D distribution over $[0,1)$, sampled by inverting its CDF.
+pub struct Distribution1D {
+  func: Vec<f32>,
+  cdf: Vec<f32>,
+  func_int: f32,
+}
+
+impl Distribution1D {
+  pub fn new(func: &[f32]) -> Self {
+    let n = func.len();
+    let func = func.iter().map(|f| f.abs()).collect::<Vec<_>>();
+    let mut cdf = vec![0.0; n + 1];
+    for i in 1..=n {
+      cdf[i] = cdf[i - 1] + func[i - 1] / n as f32;
+    }
+    let func_int = cdf[n];
+    if func_int == 0.0 {
+      // fall back to a uniform distribution
+      for (i, c) in cdf.iter_mut().enumerate().skip(1) {
+        *c = i as f32 / n as f32;
+      }
+    } else {
+      for c in cdf.iter_mut().skip(1) {
+        *c /= func_int;
+      }
+    }
+    Self {
+      func,
+      cdf,
+      func_int,
+    }
+  }
+
+  pub fn count(&self) -> usize {
+    self.func.len()
+  }
+
+  pub fn integral(&self) -> f32 {
+    self.func_int
+  }
+
+  /// Sample a continuous value, returning it along with its density and the segment it fell in.
+  pub fn sample_continuous(&self, u: f32) -> (f32, f32, usize) {
+    // find the last cdf entry that is <= u
+    let offset = self
+      .cdf
+      .partition_point(|c| *c <= u)
+      .saturating_sub(1)
+      .min(self.count() - 1);
+    let mut du = u - self.cdf[offset];
+    let width = self.cdf[offset + 1] - self.cdf[offset];
+    if width > 0.0 {
+      du /= width;
+    }
+    let pdf = if self.func_int > 0.0 {
+      self.func[offset] / self.func_int
+    } else {
+      1.0
+    };
+    (
+      ((offset as f32 + du) / self.count() as f32).min(1.0 - f32::EPSILON),
+      pdf,
+      offset,
+    )
+  }
+
+  /// Density of the segment containing `x`.
+  pub fn pdf(&self, x: f32) -> f32 {
+    let offset = ((x * self.count() as f32) as usize).min(self.count() - 1);
+    if self.func_int > 0.0 {
+      self.func[offset] / self.func_int
+    } else {
+      1.0
+    }
+  }
+}
+
+/// Piecewise-constant 2D distribution over $[0,1)^2$, built from a marginal density over rows and
+/// conditional densities within each row.
+pub struct Distribution2D {
+  conditional: Vec<Distribution1D>,
+  marginal: Distribution1D,
+}
+
+impl Distribution2D {
+  /// `func` is laid out row by row, `nu` values per row.
+  pub fn new(func: &[f32], nu: usize, nv: usize) -> Self {
+    let conditional = (0..nv)
+      .map(|v| Distribution1D::new(&func[v * nu..(v + 1) * nu]))
+      .collect::<Vec<_>>();
+    let marginal_func = conditional.iter().map(|c| c.integral()).collect::<Vec<_>>();
+    Self {
+      conditional,
+      marginal: Distribution1D::new(&marginal_func),
+    }
+  }
+
+  pub fn sample_continuous(&self, u: &glam::Vec2) -> (glam::Vec2, f32) {
+    let (d1, pdf1, v) = self.marginal.sample_continuous(u.y);
+    let (d0, pdf0, _) = self.conditional[v].sample_continuous(u.x);
+    (glam::Vec2::new(d0, d1), pdf0 * pdf1)
+  }
+
+  pub fn pdf(&self, p: &glam::Vec2) -> f32 {
+    let v = ((p.y * self.marginal.count() as f32) as usize).min(self.marginal.count() - 1);
+    self.marginal.pdf(p.y) * self.conditional[v].pdf(p.x)
+  }
+}
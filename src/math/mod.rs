@@ -1,8 +1,10 @@
 mod color;
+mod distribution;
 mod ray;
 use std::f32::consts::PI;
 
 pub use color::*;
+pub use distribution::*;
 pub use ray::*;
 
 /// Uniformly distribute samples over a unit disk.
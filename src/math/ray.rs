@@ -1,10 +1,13 @@
 use glam::Vec3A;
 
+#[derive(Clone, Copy)]
 pub struct Ray {
   pub origin: Vec3A,
   pub direction: Vec3A,
   pub t_min: f32,
   pub t_max: f32,
+  /// Time within the frame, in the same units as transform keyframes.
+  pub time: f32,
 }
 
 impl Ray {
@@ -14,6 +17,7 @@ impl Ray {
       direction,
       t_min: 0.0,
       t_max: f32::INFINITY,
+      time: 0.0,
     }
   }
 }
\ No newline at end of file
@@ -1,6 +1,16 @@
 use crate::{core::Node, gfx::Transform};
 use specs::{Component, DenseVecStorage};
 use specs_derive::Component;
+use std::{fs, io, path::Path, sync::Arc};
+
+/// How a fisheye lens maps the angle from its axis to the distance from the image center.
+#[derive(Clone, Copy, Debug)]
+pub enum FisheyeMapping {
+  /// Distance proportional to the angle.
+  Equidistant,
+  /// Distance proportional to the sine of half the angle, preserving solid angle.
+  Equisolid,
+}
 
 pub enum Projection {
   Perspective {
@@ -13,15 +23,141 @@ pub enum Projection {
     left: f32,
     right: f32,
   },
+  /// Full sphere latitude-longitude panorama.
+  Equirectangular,
+  Fisheye {
+    field_of_view: f32,
+    aspect: f32,
+    mapping: FisheyeMapping,
+  },
+  /// Over/under stereo panorama, left eye on top.
+  OmniStereo {
+    interpupillary_distance: f32,
+  },
+  /// Rays traced through a multi-element lens onto a film of the given diagonal, in meters.
+  Realistic {
+    prescription: Arc<LensPrescription>,
+    film_diagonal: f32,
+    aspect: f32,
+    /// Distance from the film to the plane in focus.
+    focus_distance: f32,
+  },
+}
+
+/// One spherical interface of a lens system, in meters. A curvature radius of zero marks the
+/// aperture stop.
+#[derive(Clone, Copy, Debug)]
+pub struct LensElement {
+  pub curvature_radius: f32,
+  /// Distance along the axis to the next interface, or to the film for the last one.
+  pub thickness: f32,
+  /// Index of refraction of the medium behind the interface.
+  pub eta: f32,
+  pub aperture_radius: f32,
+}
+
+#[derive(Debug)]
+pub enum LensError {
+  Io(io::Error),
+  Parse { line: usize, message: String },
+}
+
+impl From<io::Error> for LensError {
+  fn from(e: io::Error) -> Self {
+    LensError::Io(e)
+  }
+}
+
+/// Lens interfaces ordered from the front of the lens towards the film.
+#[derive(Clone, Debug)]
+pub struct LensPrescription {
+  pub elements: Vec<LensElement>,
+}
+
+impl LensPrescription {
+  /// Parse a pbrt-v3 style lens table. Every line holds the curvature radius, thickness, index
+  /// of refraction and aperture diameter of one interface, in millimeters, and `#` starts a
+  /// comment. An index of refraction of zero stands for air.
+  pub fn parse(text: &str) -> Result<Self, LensError> {
+    let mut elements = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+      let line = line.split('#').next().unwrap_or_default().trim();
+      if line.is_empty() {
+        continue;
+      }
+      let error = |message: String| LensError::Parse {
+        line: index + 1,
+        message,
+      };
+      let values = line
+        .split_whitespace()
+        .map(|v| v.parse::<f32>().map_err(|e| error(e.to_string())))
+        .collect::<Result<Vec<_>, _>>()?;
+      if values.len() != 4 {
+        return Err(error(format!("expected 4 values, found {}", values.len())));
+      }
+      elements.push(LensElement {
+        curvature_radius: values[0] * 0.001,
+        thickness: values[1] * 0.001,
+        eta: if values[2] == 0.0 { 1.0 } else { values[2] },
+        aperture_radius: values[3] * 0.001 * 0.5,
+      });
+    }
+    if elements.is_empty() {
+      return Err(LensError::Parse {
+        line: 0,
+        message: "lens table has no elements".into(),
+      });
+    }
+    Ok(Self { elements })
+  }
+
+  pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, LensError> {
+    Self::parse(&fs::read_to_string(path)?)
+  }
+}
+
+/// Thin lens parameters used for depth of field. An aperture radius of zero gives a pinhole.
+#[derive(Clone, Copy, Debug)]
+pub struct Lens {
+  pub aperture_radius: f32,
+  /// Distance along the view direction of the plane that is in perfect focus.
+  pub focus_distance: f32,
+  /// Number of aperture blades, or 0 for a circular aperture.
+  pub blades: u32,
+  /// Rotation of the aperture polygon, in radians.
+  pub blade_rotation: f32,
+  /// Horizontal squeeze factor of an anamorphic lens; 1 for a spherical lens. A factor of 2
+  /// makes out of focus highlights twice as tall as they are wide.
+  pub anamorphic_squeeze: f32,
+}
+
+impl Default for Lens {
+  fn default() -> Self {
+    Self {
+      aperture_radius: 0.0,
+      focus_distance: 1.0,
+      blades: 0,
+      blade_rotation: 0.0,
+      anamorphic_squeeze: 1.0,
+    }
+  }
 }
 
 #[derive(Component)]
 pub struct Camera {
   pub projection: Projection,
   pub clipping_planes: (f32, f32),
+  pub lens: Lens,
+  /// Times at which the shutter opens and closes, on the scale where transform keyframes sit at
+  /// 0 and 1. Objects with animated transforms are blurred over this interval.
+  pub shutter: (f32, f32),
 }
 impl Camera {
   pub fn perspective(fov_y: f32, aspect: f32, near: f32, far: f32) -> Node {
+    Self::thin_lens(fov_y, aspect, near, far, Lens::default())
+  }
+  pub fn thin_lens(fov_y: f32, aspect: f32, near: f32, far: f32, lens: Lens) -> Node {
     let node = Node::new();
     node.add_component(Transform::default());
     node.add_component(Camera {
@@ -30,9 +166,82 @@ impl Camera {
         aspect,
       },
       clipping_planes: (near, far),
+      lens,
+      shutter: (0.0, 1.0),
+    });
+    node
+  }
+  pub fn orthographic(left: f32, right: f32, bottom: f32, top: f32, near: f32, far: f32) -> Node {
+    let node = Node::new();
+    node.add_component(Transform::default());
+    node.add_component(Camera {
+      projection: Projection::Orthographic {
+        top,
+        bottom,
+        left,
+        right,
+      },
+      clipping_planes: (near, far),
+      lens: Lens::default(),
+      shutter: (0.0, 1.0),
+    });
+    node
+  }
+  pub fn equirectangular(near: f32, far: f32) -> Node {
+    Self::with_projection(Projection::Equirectangular, near, far)
+  }
+  pub fn fisheye(fov: f32, aspect: f32, mapping: FisheyeMapping, near: f32, far: f32) -> Node {
+    Self::with_projection(
+      Projection::Fisheye {
+        field_of_view: fov,
+        aspect,
+        mapping,
+      },
+      near,
+      far,
+    )
+  }
+  pub fn omni_stereo(interpupillary_distance: f32, near: f32, far: f32) -> Node {
+    Self::with_projection(
+      Projection::OmniStereo {
+        interpupillary_distance,
+      },
+      near,
+      far,
+    )
+  }
+  pub fn realistic(
+    prescription: Arc<LensPrescription>,
+    film_diagonal: f32,
+    aspect: f32,
+    focus_distance: f32,
+    near: f32,
+    far: f32,
+  ) -> Node {
+    Self::with_projection(
+      Projection::Realistic {
+        prescription,
+        film_diagonal,
+        aspect,
+        focus_distance,
+      },
+      near,
+      far,
+    )
+  }
+  fn with_projection(projection: Projection, near: f32, far: f32) -> Node {
+    let node = Node::new();
+    node.add_component(Transform::default());
+    node.add_component(Camera {
+      projection,
+      clipping_planes: (near, far),
+      lens: Lens::default(),
+      shutter: (0.0, 1.0),
     });
     node
   }
+  /// Projection matrix for the rasterizer. Panoramic projections have no matrix form and are
+  /// previewed with a 90 degree perspective instead.
   pub fn projection(&self) -> glam::Mat4 {
     let (near, far) = self.clipping_planes;
     match &self.projection {
@@ -45,7 +254,71 @@ impl Camera {
         bottom,
         left,
         right,
-      } => todo!(),
+      } => glam::Mat4::orthographic_rh(*left, *right, *bottom, *top, near, far),
+      Projection::Fisheye { aspect, .. } => {
+        glam::Mat4::perspective_rh(90f32.to_radians(), *aspect, near, far)
+      }
+      Projection::Equirectangular => glam::Mat4::perspective_rh(90f32.to_radians(), 2.0, near, far),
+      Projection::OmniStereo { .. } => {
+        glam::Mat4::perspective_rh(90f32.to_radians(), 1.0, near, far)
+      }
+      Projection::Realistic {
+        prescription,
+        film_diagonal,
+        aspect,
+        ..
+      } => {
+        // approximate the lens by a pinhole at its back focal distance
+        let film_height = film_diagonal / (1.0 + aspect * aspect).sqrt();
+        let back_focus = prescription
+          .elements
+          .last()
+          .map(|e| e.thickness)
+          .filter(|t| *t > 0.0)
+          .unwrap_or(0.05);
+        let fov_y = 2.0 * (0.5 * film_height / back_focus).atan();
+        glam::Mat4::perspective_rh(fov_y, *aspect, near, far)
+      }
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parse_lens_table() {
+    let lens = LensPrescription::parse(
+      "# radius thickness eta aperture
+      29.475 3.76 1.67 25.2 # front
+
+      0 4.5 0 17.1
+      -39.73 5 1 20",
+    )
+    .unwrap();
+    assert_eq!(lens.elements.len(), 3);
+    let front = lens.elements[0];
+    // millimeters are converted to meters and diameters to radii
+    assert!((front.curvature_radius - 0.029475).abs() < 1e-7);
+    assert!((front.thickness - 0.00376).abs() < 1e-7);
+    assert_eq!(front.eta, 1.67);
+    assert!((front.aperture_radius - 0.0126).abs() < 1e-7);
+    let stop = lens.elements[1];
+    assert_eq!(stop.curvature_radius, 0.0);
+    assert_eq!(stop.eta, 1.0);
+    assert!((stop.aperture_radius - 0.00855).abs() < 1e-7);
+    assert!(lens.elements[2].curvature_radius < 0.0);
+  }
+
+  #[test]
+  fn parse_errors() {
+    let line = |text: &str| match LensPrescription::parse(text) {
+      Err(LensError::Parse { line, .. }) => line,
+      _ => panic!("{text:?} was accepted"),
+    };
+    assert_eq!(line("1 2 3 4\n1 2 3"), 2);
+    assert_eq!(line("# comment\n1 2 x 4"), 2);
+    assert_eq!(line("# no elements\n"), 0);
+  }
+}
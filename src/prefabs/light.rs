@@ -0,0 +1,76 @@
+use crate::{core::Node, gfx::Transform, math::Color};
+use specs::{Component, DenseVecStorage};
+use specs_derive::Component;
+
+/// Rotation that points the node's -Z axis along `direction`.
+fn facing(direction: glam::Vec3) -> glam::Quat {
+  glam::Quat::from_rotation_arc(glam::Vec3::NEG_Z, direction.normalize())
+}
+
+/// Light emitting uniformly in all directions from the node's position.
+#[derive(Component)]
+pub struct PointLight {
+  pub color: Color,
+  pub intensity: f32,
+}
+impl PointLight {
+  pub fn new(color: Color, intensity: f32) -> Self {
+    Self { color, intensity }
+  }
+  pub fn into_node(self, position: glam::Vec3) -> Node {
+    let node = Node::new();
+    node.add_component(Transform::from_translation(position));
+    node.add_component(self);
+    node
+  }
+}
+
+/// Light emitting a cone along the node's -Z axis. Angles are half-angles in radians; the
+/// intensity falls off smoothly between the inner and the outer cone.
+#[derive(Component)]
+pub struct SpotLight {
+  pub color: Color,
+  pub intensity: f32,
+  pub inner_angle: f32,
+  pub outer_angle: f32,
+}
+impl SpotLight {
+  pub fn new(color: Color, intensity: f32, inner_angle: f32, outer_angle: f32) -> Self {
+    Self {
+      color,
+      intensity,
+      inner_angle,
+      outer_angle,
+    }
+  }
+  pub fn into_node(self, position: glam::Vec3, direction: glam::Vec3) -> Node {
+    let node = Node::new();
+    node.add_component(Transform::from_translation_rotation(
+      position,
+      facing(direction),
+    ));
+    node.add_component(self);
+    node
+  }
+}
+
+/// Light arriving from infinitely far away along the node's -Z axis.
+#[derive(Component)]
+pub struct DirectionalLight {
+  pub color: Color,
+  pub intensity: f32,
+}
+impl DirectionalLight {
+  pub fn new(color: Color, intensity: f32) -> Self {
+    Self { color, intensity }
+  }
+  pub fn into_node(self, direction: glam::Vec3) -> Node {
+    let node = Node::new();
+    node.add_component(Transform::from_translation_rotation(
+      glam::Vec3::ZERO,
+      facing(direction),
+    ));
+    node.add_component(self);
+    node
+  }
+}
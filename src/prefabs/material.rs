@@ -0,0 +1,221 @@
+use super::{FloatTexture, Texture};
+use crate::math::Color;
+use specs::{Component, DenseVecStorage};
+use specs_derive::Component;
+
+/// Metals with a measured complex index of refraction.
+#[derive(Clone, Copy, Debug)]
+pub enum Metal {
+  Gold,
+  Copper,
+  Aluminium,
+  Silver,
+}
+
+impl Metal {
+  /// Real and imaginary parts of the index of refraction, sampled at red, green and blue
+  /// wavelengths.
+  pub fn ior(&self) -> (Color, Color) {
+    match self {
+      Metal::Gold => (
+        Color::new(0.143, 0.374, 1.442),
+        Color::new(3.983, 2.385, 1.603),
+      ),
+      Metal::Copper => (
+        Color::new(0.200, 0.924, 1.102),
+        Color::new(3.912, 2.452, 2.142),
+      ),
+      Metal::Aluminium => (
+        Color::new(1.657, 0.880, 0.521),
+        Color::new(9.224, 6.270, 4.837),
+      ),
+      Metal::Silver => (
+        Color::new(0.155, 0.117, 0.138),
+        Color::new(4.828, 3.122, 2.147),
+      ),
+    }
+  }
+}
+
+/// Parameters of the principled BSDF. They follow the glTF metallic-roughness material and its
+/// specular, sheen, clearcoat, transmission and IOR extensions one to one, defaults included.
+/// Color parameters have type `C` and scalar ones type `F`, textures unless evaluated.
+#[derive(Clone)]
+pub struct Principled<C = Texture, F = FloatTexture> {
+  pub base_color: C,
+  pub metallic: F,
+  pub roughness: F,
+  /// Strength of the dielectric specular reflection.
+  pub specular: F,
+  /// Color of the dielectric specular reflection at normal incidence.
+  pub specular_tint: C,
+  pub sheen: C,
+  pub sheen_roughness: F,
+  pub clearcoat: F,
+  pub clearcoat_roughness: F,
+  /// Fraction of the non-metallic base that lets light through instead of scattering it
+  /// diffusely.
+  pub transmission: F,
+  pub ior: F,
+}
+
+impl<C, F> Principled<C, F> {
+  /// Convert every color parameter with `color` and every scalar one with `scalar`.
+  pub fn map<D, G>(&self, color: impl Fn(&C) -> D, scalar: impl Fn(&F) -> G) -> Principled<D, G> {
+    Principled {
+      base_color: color(&self.base_color),
+      metallic: scalar(&self.metallic),
+      roughness: scalar(&self.roughness),
+      specular: scalar(&self.specular),
+      specular_tint: color(&self.specular_tint),
+      sheen: color(&self.sheen),
+      sheen_roughness: scalar(&self.sheen_roughness),
+      clearcoat: scalar(&self.clearcoat),
+      clearcoat_roughness: scalar(&self.clearcoat_roughness),
+      transmission: scalar(&self.transmission),
+      ior: scalar(&self.ior),
+    }
+  }
+}
+
+impl<C: From<Color>, F: From<f32>> Default for Principled<C, F> {
+  fn default() -> Self {
+    Self {
+      base_color: Color::WHITE.into(),
+      metallic: 1.0.into(),
+      roughness: 1.0.into(),
+      specular: 1.0.into(),
+      specular_tint: Color::WHITE.into(),
+      sheen: Color::BLACK.into(),
+      sheen_roughness: 0.0.into(),
+      clearcoat: 0.0.into(),
+      clearcoat_roughness: 0.0.into(),
+      transmission: 0.0.into(),
+      ior: 1.5.into(),
+    }
+  }
+}
+
+#[derive(Clone)]
+pub enum Bsdf {
+  Lambertian {
+    albedo: Texture,
+  },
+  /// Glass-like interface with the given index of refraction. A roughness of zero gives a
+  /// perfectly smooth surface.
+  Dielectric {
+    ior: FloatTexture,
+    roughness: FloatTexture,
+  },
+  /// Metal with complex index of refraction `eta + i k`. The roughness along the surface
+  /// tangent `u` and across it `v` may differ, for brushed finishes.
+  Conductor {
+    eta: Texture,
+    k: Texture,
+    roughness_u: FloatTexture,
+    roughness_v: FloatTexture,
+  },
+  Principled(Box<Principled>),
+  /// Invisible surface that lets light through untouched, only bounding a medium.
+  Interface,
+}
+
+/// Perturbation of the shading normal by a texture.
+#[derive(Clone)]
+pub enum NormalMapping {
+  /// Tangent space normals encoded as linear colors, with the tangent along increasing u and
+  /// the bitangent the normal crossed with it, as glTF does for meshes without tangents.
+  /// `scale` multiplies the tangential components, like the glTF normal texture scale.
+  Normal { texture: Texture, scale: f32 },
+  /// Height field that displaces the surface along its normal by `scale` times its value.
+  Bump { height: FloatTexture, scale: f32 },
+}
+
+#[derive(Component, Clone)]
+pub struct Material {
+  pub bsdf: Bsdf,
+  /// Radiance leaving the front side of the surface, which also turns it into an area light.
+  pub emission: Texture,
+  pub normal_mapping: Option<NormalMapping>,
+}
+impl Material {
+  pub fn lambertian(albedo: impl Into<Texture>) -> Self {
+    Self {
+      bsdf: Bsdf::Lambertian {
+        albedo: albedo.into(),
+      },
+      emission: Texture::Constant(Color::BLACK),
+      normal_mapping: None,
+    }
+  }
+  pub fn dielectric(ior: impl Into<FloatTexture>, roughness: impl Into<FloatTexture>) -> Self {
+    Self {
+      bsdf: Bsdf::Dielectric {
+        ior: ior.into(),
+        roughness: roughness.into(),
+      },
+      emission: Texture::Constant(Color::BLACK),
+      normal_mapping: None,
+    }
+  }
+  pub fn conductor(
+    eta: impl Into<Texture>,
+    k: impl Into<Texture>,
+    roughness_u: impl Into<FloatTexture>,
+    roughness_v: impl Into<FloatTexture>,
+  ) -> Self {
+    Self {
+      bsdf: Bsdf::Conductor {
+        eta: eta.into(),
+        k: k.into(),
+        roughness_u: roughness_u.into(),
+        roughness_v: roughness_v.into(),
+      },
+      emission: Texture::Constant(Color::BLACK),
+      normal_mapping: None,
+    }
+  }
+  pub fn metal(metal: Metal, roughness: impl Into<FloatTexture>) -> Self {
+    let (eta, k) = metal.ior();
+    let roughness = roughness.into();
+    Self::conductor(eta, k, roughness.clone(), roughness)
+  }
+  pub fn principled(principled: Principled) -> Self {
+    Self {
+      bsdf: Bsdf::Principled(Box::new(principled)),
+      emission: Texture::Constant(Color::BLACK),
+      normal_mapping: None,
+    }
+  }
+  pub fn interface() -> Self {
+    Self {
+      bsdf: Bsdf::Interface,
+      emission: Texture::Constant(Color::BLACK),
+      normal_mapping: None,
+    }
+  }
+  /// Emitted radiance leaving the front side of the surface.
+  pub fn with_emission(mut self, emission: impl Into<Texture>) -> Self {
+    self.emission = emission.into();
+    self
+  }
+  /// Perturb the shading normal with a tangent space normal map. The texture should be loaded
+  /// with `ColorEncoding::Linear`.
+  pub fn with_normal_map(mut self, texture: Texture, scale: f32) -> Self {
+    self.normal_mapping = Some(NormalMapping::Normal { texture, scale });
+    self
+  }
+  /// Perturb the shading normal with a bump map.
+  pub fn with_bump_map(mut self, height: impl Into<FloatTexture>, scale: f32) -> Self {
+    self.normal_mapping = Some(NormalMapping::Bump {
+      height: height.into(),
+      scale,
+    });
+    self
+  }
+}
+impl Default for Material {
+  fn default() -> Self {
+    Self::lambertian(Color::new(0.8, 0.8, 0.8))
+  }
+}
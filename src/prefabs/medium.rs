@@ -0,0 +1,31 @@
+use crate::math::Color;
+use specs::{Component, DenseVecStorage};
+use specs_derive::Component;
+
+/// Homogeneous participating medium. Attached to a node with a closed mesh, it fills the inside
+/// of the mesh; without a material on the node, the mesh surface itself is invisible. It can
+/// also fill the whole scene with `SceneEngine::set_medium`. Coefficients are per unit length.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Medium {
+  pub sigma_a: Color,
+  pub sigma_s: Color,
+  /// Asymmetry of the Henyey-Greenstein phase function, in $(-1,1)$.
+  pub g: f32,
+}
+impl Medium {
+  pub fn new(sigma_a: Color, sigma_s: Color, g: f32) -> Self {
+    Self {
+      sigma_a,
+      sigma_s,
+      g,
+    }
+  }
+  /// Gray medium that scatters `albedo` of the light it extinguishes.
+  pub fn fog(density: f32, albedo: f32, g: f32) -> Self {
+    Self::new(
+      Color::splat(density * (1.0 - albedo)),
+      Color::splat(density * albedo),
+      g,
+    )
+  }
+}
@@ -1,4 +1,5 @@
 use std::f32::consts::PI;
+use std::sync::Arc;
 
 use crate::{core::Node, gfx::Transform};
 use specs::{Component, DenseVecStorage};
@@ -6,7 +7,7 @@ use specs_derive::Component;
 
 #[derive(Component)]
 pub struct Mesh {
-  data: Option<Box<MeshData>>,
+  data: Option<Arc<MeshData>>,
 }
 impl Mesh {
   pub fn new(
@@ -16,7 +17,7 @@ impl Mesh {
     indices: Option<Vec<u32>>,
   ) -> Self {
     Self {
-      data: Some(Box::new(MeshData {
+      data: Some(Arc::new(MeshData {
         vertices,
         normals,
         uvs,
@@ -36,6 +37,16 @@ impl Mesh {
     }
     Err(MeshAccessError::MeshDataDropped)
   }
+  /// Another mesh sharing the data of this one. The ray tracer builds a single BVH for all meshes
+  /// that share their data, however many nodes they are placed on.
+  pub fn try_instance(&self) -> Result<Self, MeshAccessError> {
+    match &self.data {
+      Some(data) => Ok(Self {
+        data: Some(data.clone()),
+      }),
+      None => Err(MeshAccessError::MeshDataDropped),
+    }
+  }
 }
 
 #[derive(Debug)]
@@ -1,6 +1,16 @@
 pub mod geom;
 pub mod camera;
+pub mod light;
+pub mod material;
+pub mod medium;
 pub mod mesh;
+pub mod texture;
+pub mod volume;
 pub use geom::*;
 pub use camera::*;
-pub use mesh::*;
\ No newline at end of file
+pub use light::*;
+pub use material::*;
+pub use medium::*;
+pub use mesh::*;
+pub use texture::*;
+pub use volume::*;
\ No newline at end of file
@@ -0,0 +1,135 @@
+use crate::{
+  math::Color,
+  raytrace::{ColorEncoding, Image, ImageError},
+};
+use glam::Vec2;
+use std::{path::Path, sync::Arc};
+
+/// How texture coordinates outside of $[0,1]$ are brought back onto the image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WrapMode {
+  Repeat,
+  Clamp,
+  Mirror,
+}
+
+/// Transform applied to texture coordinates before the lookup: they are scaled, rotated
+/// counterclockwise by `rotation` radians and then offset, as in `KHR_texture_transform`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UvTransform {
+  pub offset: Vec2,
+  pub scale: Vec2,
+  pub rotation: f32,
+}
+
+impl Default for UvTransform {
+  fn default() -> Self {
+    Self {
+      offset: Vec2::ZERO,
+      scale: Vec2::ONE,
+      rotation: 0.0,
+    }
+  }
+}
+
+/// How a point on a surface finds its texture coordinates.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextureMapping {
+  /// The texture coordinates of the shape.
+  Uv(UvTransform),
+  /// Three planar projections of the object space position along its axes, blended by the
+  /// normal. Needs no texture coordinates. `scale` is the number of repetitions per unit and a
+  /// larger `sharpness` narrows the blend between projections.
+  Triplanar { scale: f32, sharpness: f32 },
+}
+
+impl Default for TextureMapping {
+  fn default() -> Self {
+    TextureMapping::Uv(UvTransform::default())
+  }
+}
+
+#[derive(Clone)]
+pub enum Texture {
+  Constant(Color),
+  /// Alternating unit squares of two colors.
+  Checkerboard {
+    even: Color,
+    odd: Color,
+    mapping: TextureMapping,
+  },
+  /// The fractional texture coordinates in the red and green channels.
+  UvDebug {
+    mapping: TextureMapping,
+  },
+  /// Bilinearly filtered image, with the origin of the texture coordinates at its top left.
+  Image {
+    image: Arc<Image>,
+    wrap: WrapMode,
+    mapping: TextureMapping,
+  },
+}
+
+impl Texture {
+  pub fn checkerboard(even: Color, odd: Color, mapping: TextureMapping) -> Self {
+    Texture::Checkerboard { even, odd, mapping }
+  }
+  pub fn uv_debug(mapping: TextureMapping) -> Self {
+    Texture::UvDebug { mapping }
+  }
+  pub fn image(image: Arc<Image>, wrap: WrapMode, mapping: TextureMapping) -> Self {
+    Texture::Image {
+      image,
+      wrap,
+      mapping,
+    }
+  }
+  /// Load an image texture that repeats over the texture coordinates of the shape.
+  pub fn load<P: AsRef<Path>>(path: P, encoding: ColorEncoding) -> Result<Self, ImageError> {
+    let image = Image::load_with_encoding(path, encoding)?;
+    Ok(Self::image(
+      Arc::new(image),
+      WrapMode::Repeat,
+      TextureMapping::default(),
+    ))
+  }
+}
+
+impl From<Color> for Texture {
+  fn from(color: Color) -> Self {
+    Texture::Constant(color)
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Channel {
+  Red,
+  Green,
+  Blue,
+}
+
+/// Scalar parameter read from one channel of a texture, such as the roughness in the green
+/// channel of a glTF metallic-roughness map.
+#[derive(Clone)]
+pub struct FloatTexture {
+  pub texture: Texture,
+  pub channel: Channel,
+}
+
+impl FloatTexture {
+  pub fn new(texture: Texture, channel: Channel) -> Self {
+    Self { texture, channel }
+  }
+}
+
+impl From<f32> for FloatTexture {
+  fn from(value: f32) -> Self {
+    Self::new(Texture::Constant(Color::splat(value)), Channel::Red)
+  }
+}
+
+impl From<Texture> for FloatTexture {
+  fn from(texture: Texture) -> Self {
+    Self::new(texture, Channel::Red)
+  }
+}
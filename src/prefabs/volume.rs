@@ -0,0 +1,51 @@
+use crate::{core::Node, gfx::Transform, math::Color, raytrace::VoxelGrid};
+use specs::{Component, DenseVecStorage};
+use specs_derive::Component;
+use std::sync::Arc;
+
+/// Heterogeneous participating medium filling the box of a density grid, placed by the
+/// transform of its node. The extinction at a point is `sigma_t` times the density there, of
+/// which `albedo` scatters. Optional grids add emission, either as a color or as a temperature
+/// in kelvin glowing like a black body, both scaled by `emission_scale`.
+#[derive(Component, Clone)]
+pub struct Volume {
+  pub density: Arc<VoxelGrid>,
+  pub sigma_t: f32,
+  pub albedo: Color,
+  /// Asymmetry of the Henyey-Greenstein phase function, in $(-1,1)$.
+  pub g: f32,
+  pub emission: Option<Arc<VoxelGrid>>,
+  pub temperature: Option<Arc<VoxelGrid>>,
+  pub emission_scale: f32,
+}
+impl Volume {
+  pub fn new(density: Arc<VoxelGrid>, sigma_t: f32, albedo: Color, g: f32) -> Self {
+    Self {
+      density,
+      sigma_t,
+      albedo,
+      g,
+      emission: None,
+      temperature: None,
+      emission_scale: 1.0,
+    }
+  }
+  pub fn with_emission(mut self, emission: Arc<VoxelGrid>) -> Self {
+    self.emission = Some(emission);
+    self
+  }
+  pub fn with_temperature(mut self, temperature: Arc<VoxelGrid>) -> Self {
+    self.temperature = Some(temperature);
+    self
+  }
+  pub fn with_emission_scale(mut self, emission_scale: f32) -> Self {
+    self.emission_scale = emission_scale;
+    self
+  }
+  pub fn into_node(self, transform: Transform) -> Node {
+    let node = Node::new();
+    node.add_component(transform);
+    node.add_component(self);
+    node
+  }
+}
@@ -1,7 +1,12 @@
 use super::{
+  environment::Sky,
   hit::Hit,
+  light::Light,
+  material::Material,
+  medium::{HomogeneousMedium, Medium},
   scene::{Primitive, SceneEngine},
-  shape::{Shape, Sphere, Triangle},
+  shape::{Shape, Sphere, Triangle, TriangleMesh},
+  transform::AnimatedTransform,
 };
 use crate::math::Ray;
 use bvh::{
@@ -9,12 +14,39 @@ use bvh::{
   bounding_hierarchy::BHShape,
   bvh::BVH,
 };
-use std::collections::VecDeque;
+use std::{
+  collections::{HashMap, VecDeque},
+  sync::Arc,
+};
+
+/// Bottom level hierarchy over the shapes of one primitive, in object space. Every instance of
+/// the primitive shares it.
+struct Blas {
+  bvh: BVH,
+  nodes: Vec<L2Node>,
+  bound: AABB,
+}
+impl Blas {
+  fn build(mut nodes: Vec<L2Node>) -> Self {
+    let mut bound = AABB::empty();
+    for node in &nodes {
+      bound.join_mut(&node.shape.aabb());
+    }
+    Self {
+      bvh: BVH::build(&mut nodes),
+      nodes,
+      bound,
+    }
+  }
+}
 
+/// Top level node: an instance of a shared [`Blas`] placed in the world by its transform. The
+/// world space bound covers the whole motion of an animated transform.
 struct L1Node {
-  l2_bvh: BVH,
+  blas: Arc<Blas>,
   bound: AABB,
-  l2nodes: Vec<L2Node>,
+  transform: Arc<AnimatedTransform>,
+  material: Arc<Material>,
   node_index: usize,
 }
 impl Bounded for L1Node {
@@ -31,6 +63,13 @@ impl BHShape for L1Node {
   }
 }
 
+fn bvh_ray(ray: &Ray) -> bvh::ray::Ray {
+  bvh::ray::Ray::new(
+    bvh::Point3::new(ray.origin.x, ray.origin.y, ray.origin.z),
+    bvh::Vector3::new(ray.direction.x, ray.direction.y, ray.direction.z),
+  )
+}
+
 struct L2Node {
   shape: Shape,
   node_index: usize,
@@ -52,47 +91,72 @@ impl BHShape for L2Node {
 pub struct Accelerator {
   l1_bvh: BVH,
   l1nodes: Vec<L1Node>,
+  lights: Vec<Light>,
+  sky: Sky,
+  medium: Option<Arc<dyn Medium>>,
 }
 impl Accelerator {
   pub(super) fn build(scene: &SceneEngine) -> Self {
     let mut l1nodes = Vec::new();
+    let mut lights = Vec::new();
+    if let Sky::Environment(map) = &scene.sky {
+      lights.push(Light::Environment(map.clone()));
+    }
+    // instances of the same mesh or sphere radius share one bottom level hierarchy
+    let mut meshes = HashMap::<*const TriangleMesh, Arc<Blas>>::new();
+    let mut spheres = HashMap::<u32, Arc<Blas>>::new();
     let mut stack = VecDeque::new();
     stack.push_back(&scene.root);
     while !stack.is_empty() {
       if let Some(current_node) = stack.pop_front() {
         // Process primitive
-        let mut bound = AABB::empty();
-        let mut l2nodes = Vec::new();
-        match &current_node.prim {
-          Primitive::Empty => (),
-          Primitive::Sphere(center, radius) => {
-            let sphere = Sphere::new(*center, *radius);
-            bound.join_mut(&sphere.aabb());
-            l2nodes.push(L2Node {
-              shape: Shape::Sphere(sphere),
-              node_index: 0,
-            })
-          }
-          Primitive::TriangleMesh(tri_mesh) => {
-            for id in 0..tri_mesh.tri_count {
-              let triangle = Triangle::new(tri_mesh.clone(), id);
-              l2nodes.push(L2Node {
-                shape: Shape::Triangle(triangle),
+        let instance = match &current_node.prim {
+          Primitive::Sphere(radius, transform, material) => {
+            let blas = spheres.entry(radius.to_bits()).or_insert_with(|| {
+              Arc::new(Blas::build(vec![L2Node {
+                shape: Shape::Sphere(Sphere::new(glam::Vec3::ZERO, *radius)),
                 node_index: 0,
-              })
-            }
+              }]))
+            });
+            Some((blas.clone(), transform, material))
           }
-          _ => (),
-        }
+          Primitive::TriangleMesh(tri_mesh, transform, material) if tri_mesh.tri_count > 0 => {
+            let blas = meshes.entry(Arc::as_ptr(tri_mesh)).or_insert_with(|| {
+              Arc::new(Blas::build(
+                (0..tri_mesh.tri_count)
+                  .map(|id| L2Node {
+                    shape: Shape::Triangle(Triangle::new(tri_mesh.clone(), id)),
+                    node_index: 0,
+                  })
+                  .collect(),
+              ))
+            });
+            Some((blas.clone(), transform, material))
+          }
+          Primitive::Light(light) => {
+            lights.push(light.clone());
+            None
+          }
+          _ => None,
+        };
 
-        if !l2nodes.is_empty() {
-          let l1node = L1Node {
-            l2_bvh: BVH::build(&mut l2nodes),
-            bound,
-            l2nodes,
+        if let Some((blas, transform, material)) = instance {
+          if material.is_emissive() {
+            for l2 in &blas.nodes {
+              lights.push(Light::Area {
+                shape: l2.shape.clone(),
+                transform: transform.clone(),
+                material: material.clone(),
+              });
+            }
+          }
+          l1nodes.push(L1Node {
+            bound: transform.motion_bounds(&blas.bound),
+            blas,
+            transform: transform.clone(),
+            material: material.clone(),
             node_index: 0,
-          };
-          l1nodes.push(l1node);
+          });
         }
 
         // Push the remaining children
@@ -104,29 +168,89 @@ impl Accelerator {
     Self {
       l1_bvh: BVH::build(&mut l1nodes),
       l1nodes,
+      lights,
+      sky: scene.sky.clone(),
+      medium: scene
+        .medium
+        .as_ref()
+        .map(|medium| Arc::new(HomogeneousMedium::from(medium)) as Arc<dyn Medium>),
     }
   }
 
+  pub(super) fn lights(&self) -> &[Light] {
+    &self.lights
+  }
+
+  pub(super) fn sky(&self) -> &Sky {
+    &self.sky
+  }
+
+  /// Medium filling the scene outside of closed meshes with their own.
+  pub(super) fn medium(&self) -> Option<&dyn Medium> {
+    self.medium.as_deref()
+  }
+
   pub(super) fn intersect<'a>(&'a self, ray: &Ray, hit: &mut Hit<'a>) -> bool {
+    self.closest_hit(ray, hit, false)
+  }
+
+  /// Closest hit along a shadow ray. Unlike `intersect`, the back faces of opaque surfaces count
+  /// too, as they block light from both sides.
+  pub(super) fn intersect_shadow<'a>(&'a self, ray: &Ray, hit: &mut Hit<'a>) -> bool {
+    self.closest_hit(ray, hit, true)
+  }
+
+  fn closest_hit<'a>(&'a self, ray: &Ray, hit: &mut Hit<'a>, back_faces: bool) -> bool {
     let mut any_hit = false;
-    let bvh_ray = bvh::ray::Ray::new(
-      bvh::Point3::new(ray.origin.x, ray.origin.y, ray.origin.z),
-      bvh::Vector3::new(ray.direction.x, ray.direction.y, ray.direction.z),
-    );
     let mut closest_hit = f32::INFINITY;
-    for l1 in self.l1_bvh.traverse(&bvh_ray, &self.l1nodes) {
-      for l2 in l1.l2_bvh.traverse(&bvh_ray, &l1.l2nodes) {
+    for l1 in self.l1_bvh.traverse(&bvh_ray(ray), &self.l1nodes) {
+      let (object_to_world, world_to_object) = l1.transform.interpolate_with_inverse(ray.time);
+      // the direction is not normalized so that distances along the ray stay the same
+      let object_ray = Ray {
+        origin: world_to_object.transform_point3a(ray.origin),
+        direction: world_to_object.transform_vector3a(ray.direction),
+        ..*ray
+      };
+      for l2 in l1.blas.bvh.traverse(&bvh_ray(&object_ray), &l1.blas.nodes) {
         let mut tmp_hit = Hit::default();
-        if l2.shape.intersect(ray, &mut tmp_hit) && tmp_hit.front {
+        // back faces are only seen on surfaces that light can pass through
+        if l2.shape.intersect(&object_ray, &mut tmp_hit)
+          && (tmp_hit.front || back_faces || l1.material.is_transmissive())
+        {
           any_hit = true;
           if tmp_hit.t < closest_hit {
             closest_hit = tmp_hit.t;
             *hit = tmp_hit;
+            hit.transform(object_to_world, &world_to_object);
             hit.shape = Some(&l2.shape);
+            hit.material = Some(&l1.material);
           }
         }
       }
     }
     any_hit
   }
+
+  /// Test whether anything blocks the ray between `t_min` and `t_max`, from either side. Medium
+  /// boundaries without a surface never do.
+  pub(super) fn occluded(&self, ray: &Ray) -> bool {
+    for l1 in self.l1_bvh.traverse(&bvh_ray(ray), &self.l1nodes) {
+      if l1.material.is_interface() {
+        continue;
+      }
+      let (_, world_to_object) = l1.transform.interpolate_with_inverse(ray.time);
+      let object_ray = Ray {
+        origin: world_to_object.transform_point3a(ray.origin),
+        direction: world_to_object.transform_vector3a(ray.direction),
+        ..*ray
+      };
+      for l2 in l1.blas.bvh.traverse(&bvh_ray(&object_ray), &l1.blas.nodes) {
+        let mut tmp_hit = Hit::default();
+        if l2.shape.intersect(&object_ray, &mut tmp_hit) {
+          return true;
+        }
+      }
+    }
+    false
+  }
 }
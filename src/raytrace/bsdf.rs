@@ -1,12 +1,36 @@
 use std::f32::consts::PI;
 
-use super::hit::Hit;
-use crate::math::{cosine_sample_hemisphere, uniform_sample_sphere, Color};
+use super::{
+  hit::Hit,
+  microfacet::{
+    cos_theta, fresnel_conductor, fresnel_dielectric, reflect, refract, same_hemisphere,
+    TrowbridgeReitz,
+  },
+};
+use crate::{
+  math::{cosine_sample_hemisphere, Color},
+  prefabs,
+};
 use glam::Vec3A;
 
-pub(super) trait BSDF {
+pub(super) trait BSDF: Send + Sync {
   fn eval(&self, hit: &Hit, wo: &Vec3A, wi: &Vec3A, pdf: &mut f32) -> Color;
-  fn sample(&self, hit: &Hit, wo: &Vec3A, wi: &mut Vec3A, pdf: &mut f32, sample: &glam::Vec2) -> Color;
+  /// Sample an incident direction. `lobe` chooses between the lobes of BSDFs that have several,
+  /// such as reflection and transmission.
+  fn sample(
+    &self,
+    hit: &Hit,
+    wo: &Vec3A,
+    wi: &mut Vec3A,
+    pdf: &mut f32,
+    lobe: f32,
+    sample: &glam::Vec2,
+  ) -> Color;
+  /// Whether the BSDF only scatters into discrete directions, which `eval` never returns and
+  /// light sampling cannot find.
+  fn is_specular(&self) -> bool {
+    false
+  }
 }
 
 pub struct Lambertian {
@@ -35,10 +59,18 @@ impl BSDF for Lambertian {
       return Color::BLACK;
     }
     *pdf = cos_theta_i / PI;
-    self.diffuse_color
+    self.diffuse_color / PI
   }
 
-  fn sample(&self, hit: &Hit, _wo: &Vec3A, wi: &mut Vec3A, pdf: &mut f32, sample: &glam::Vec2) -> Color {
+  fn sample(
+    &self,
+    hit: &Hit,
+    _wo: &Vec3A,
+    wi: &mut Vec3A,
+    pdf: &mut f32,
+    _lobe: f32,
+    sample: &glam::Vec2,
+  ) -> Color {
     *wi = hit.local_to_world(cosine_sample_hemisphere(sample));
 
     let cos_theta_i = hit.ns.dot(*wi);
@@ -48,6 +80,606 @@ impl BSDF for Lambertian {
       return Color::BLACK;
     }
     *pdf = cos_theta_i / PI;
-    self.diffuse_color
+    self.diffuse_color / PI
+  }
+}
+
+/// Smooth or rough interface between two dielectrics, such as air and glass. Light is either
+/// reflected or refracted, in proportion to the Fresnel reflectance.
+pub struct Dielectric {
+  /// Index of refraction inside the surface over the one outside.
+  eta: f32,
+  distribution: TrowbridgeReitz,
+}
+
+impl Dielectric {
+  pub fn new(eta: f32, roughness: f32) -> Self {
+    let alpha = TrowbridgeReitz::roughness_to_alpha(roughness);
+    Self {
+      eta,
+      distribution: TrowbridgeReitz::new(alpha, alpha),
+    }
+  }
+
+  fn eval_local(&self, wo: Vec3A, wi: Vec3A, pdf: &mut f32) -> Color {
+    *pdf = 0.0;
+    if self.eta == 1.0 || self.distribution.effectively_smooth() {
+      return Color::BLACK;
+    }
+    let (cos_o, cos_i) = (cos_theta(wo), cos_theta(wi));
+    if cos_o == 0.0 || cos_i == 0.0 {
+      return Color::BLACK;
+    }
+    // generalized half vector, which is the microfacet normal for both lobes
+    let reflection = cos_o * cos_i > 0.0;
+    let etap = match (reflection, cos_o > 0.0) {
+      (true, _) => 1.0,
+      (false, true) => self.eta,
+      (false, false) => 1.0 / self.eta,
+    };
+    let wm = wi * etap + wo;
+    if wm.length_squared() == 0.0 {
+      return Color::BLACK;
+    }
+    let wm = wm.normalize();
+    let wm = if wm.z < 0.0 { -wm } else { wm };
+    // microfacets facing away from either direction cannot contribute
+    if wm.dot(wi) * cos_i < 0.0 || wm.dot(wo) * cos_o < 0.0 {
+      return Color::BLACK;
+    }
+    let r = fresnel_dielectric(wo.dot(wm), self.eta);
+    let t = 1.0 - r;
+    let d = self.distribution.d(wm);
+    let g = self.distribution.g(wo, wi);
+    if reflection {
+      *pdf = self.distribution.pdf(wo, wm) / (4.0 * wo.dot(wm).abs()) * r;
+      Color::splat(d * g * r / (4.0 * cos_i * cos_o).abs())
+    } else {
+      let denom = wi.dot(wm) + wo.dot(wm) / etap;
+      let denom = denom * denom;
+      let dwm_dwi = wi.dot(wm).abs() / denom;
+      *pdf = self.distribution.pdf(wo, wm) * dwm_dwi * t;
+      // radiance is compressed into a smaller solid angle when entering a denser medium
+      let f = t * d * g * (wi.dot(wm) * wo.dot(wm) / (cos_i * cos_o * denom)).abs();
+      Color::splat(f / (etap * etap))
+    }
+  }
+
+  fn sample_local(
+    &self,
+    wo: Vec3A,
+    wi: &mut Vec3A,
+    pdf: &mut f32,
+    lobe: f32,
+    u: &glam::Vec2,
+  ) -> Color {
+    *pdf = 0.0;
+    if self.eta == 1.0 || self.distribution.effectively_smooth() {
+      // perfect specular reflection or transmission
+      let r = fresnel_dielectric(cos_theta(wo), self.eta);
+      let t = 1.0 - r;
+      if lobe < r {
+        *wi = Vec3A::new(-wo.x, -wo.y, wo.z);
+        *pdf = r;
+        return Color::splat(r / cos_theta(*wi).abs());
+      }
+      return match refract(wo, Vec3A::Z, self.eta) {
+        Some((wt, etap)) if wt.z != 0.0 => {
+          *wi = wt;
+          *pdf = t;
+          Color::splat(t / cos_theta(wt).abs() / (etap * etap))
+        }
+        _ => Color::BLACK,
+      };
+    }
+
+    let wm = self.distribution.sample_wm(wo, u);
+    let r = fresnel_dielectric(wo.dot(wm), self.eta);
+    if lobe < r {
+      let wr = reflect(wo, wm);
+      if !same_hemisphere(wo, wr) {
+        return Color::BLACK;
+      }
+      *wi = wr;
+    } else {
+      match refract(wo, wm, self.eta) {
+        Some((wt, _)) if !same_hemisphere(wo, wt) && wt.z != 0.0 => *wi = wt,
+        _ => return Color::BLACK,
+      }
+    }
+    self.eval_local(wo, *wi, pdf)
+  }
+}
+
+impl BSDF for Dielectric {
+  fn eval(&self, hit: &Hit, wo: &Vec3A, wi: &Vec3A, pdf: &mut f32) -> Color {
+    self.eval_local(hit.world_to_local(*wo), hit.world_to_local(*wi), pdf)
+  }
+
+  fn sample(
+    &self,
+    hit: &Hit,
+    wo: &Vec3A,
+    wi: &mut Vec3A,
+    pdf: &mut f32,
+    lobe: f32,
+    sample: &glam::Vec2,
+  ) -> Color {
+    let mut local_wi = Vec3A::ZERO;
+    let f = self.sample_local(hit.world_to_local(*wo), &mut local_wi, pdf, lobe, sample);
+    *wi = hit.local_to_world(local_wi);
+    f
+  }
+
+  fn is_specular(&self) -> bool {
+    self.eta == 1.0 || self.distribution.effectively_smooth()
+  }
+}
+
+/// Smooth or rough metal. The Fresnel reflectance follows from the complex index of refraction
+/// `eta + i k` of every color channel, and the roughness may differ along `dpdu` and across it.
+pub struct Conductor {
+  eta: Color,
+  k: Color,
+  distribution: TrowbridgeReitz,
+}
+
+impl Conductor {
+  pub fn new(eta: Color, k: Color, roughness_u: f32, roughness_v: f32) -> Self {
+    Self {
+      eta,
+      k,
+      distribution: TrowbridgeReitz::new(
+        TrowbridgeReitz::roughness_to_alpha(roughness_u),
+        TrowbridgeReitz::roughness_to_alpha(roughness_v),
+      ),
+    }
+  }
+
+  fn fresnel(&self, cos_i: f32) -> Color {
+    Color::new(
+      fresnel_conductor(cos_i, self.eta.r, self.k.r),
+      fresnel_conductor(cos_i, self.eta.g, self.k.g),
+      fresnel_conductor(cos_i, self.eta.b, self.k.b),
+    )
+  }
+
+  fn eval_local(&self, wo: Vec3A, wi: Vec3A, pdf: &mut f32) -> Color {
+    *pdf = 0.0;
+    if !same_hemisphere(wo, wi) || self.distribution.effectively_smooth() {
+      return Color::BLACK;
+    }
+    let (cos_o, cos_i) = (cos_theta(wo).abs(), cos_theta(wi).abs());
+    let wm = wi + wo;
+    if cos_i == 0.0 || cos_o == 0.0 || wm.length_squared() == 0.0 {
+      return Color::BLACK;
+    }
+    let wm = wm.normalize();
+    let wm = if wm.z < 0.0 { -wm } else { wm };
+    *pdf = self.distribution.pdf(wo, wm) / (4.0 * wo.dot(wm).abs());
+    let f = self.distribution.d(wm) * self.distribution.g(wo, wi) / (4.0 * cos_i * cos_o);
+    self.fresnel(wo.dot(wm).abs()) * f
+  }
+
+  fn sample_local(&self, wo: Vec3A, wi: &mut Vec3A, pdf: &mut f32, u: &glam::Vec2) -> Color {
+    *pdf = 0.0;
+    if cos_theta(wo) == 0.0 {
+      return Color::BLACK;
+    }
+    if self.distribution.effectively_smooth() {
+      *wi = Vec3A::new(-wo.x, -wo.y, wo.z);
+      *pdf = 1.0;
+      let cos_i = cos_theta(*wi).abs();
+      return self.fresnel(cos_i) / cos_i;
+    }
+    let wm = self.distribution.sample_wm(wo, u);
+    let wr = reflect(wo, wm);
+    if !same_hemisphere(wo, wr) {
+      return Color::BLACK;
+    }
+    *wi = wr;
+    self.eval_local(wo, wr, pdf)
+  }
+}
+
+impl BSDF for Conductor {
+  fn eval(&self, hit: &Hit, wo: &Vec3A, wi: &Vec3A, pdf: &mut f32) -> Color {
+    self.eval_local(hit.world_to_local(*wo), hit.world_to_local(*wi), pdf)
+  }
+
+  fn sample(
+    &self,
+    hit: &Hit,
+    wo: &Vec3A,
+    wi: &mut Vec3A,
+    pdf: &mut f32,
+    _lobe: f32,
+    sample: &glam::Vec2,
+  ) -> Color {
+    let mut local_wi = Vec3A::ZERO;
+    let f = self.sample_local(hit.world_to_local(*wo), &mut local_wi, pdf, sample);
+    *wi = hit.local_to_world(local_wi);
+    f
+  }
+
+  fn is_specular(&self) -> bool {
+    self.distribution.effectively_smooth()
+  }
+}
+
+/// Surface that passes light straight through, bounding a medium without scattering.
+pub struct Interface;
+
+impl BSDF for Interface {
+  fn eval(&self, _hit: &Hit, _wo: &Vec3A, _wi: &Vec3A, pdf: &mut f32) -> Color {
+    *pdf = 0.0;
+    Color::BLACK
+  }
+
+  fn sample(
+    &self,
+    hit: &Hit,
+    wo: &Vec3A,
+    wi: &mut Vec3A,
+    pdf: &mut f32,
+    _lobe: f32,
+    _sample: &glam::Vec2,
+  ) -> Color {
+    *wi = -*wo;
+    *pdf = 1.0;
+    Color::WHITE / wi.dot(hit.ns).abs()
+  }
+
+  fn is_specular(&self) -> bool {
+    true
+  }
+}
+
+/// Smallest microfacet alpha of the principled lobes, which keeps them out of the perfectly
+/// specular regime so that every lobe can be evaluated and mixed.
+const MIN_PRINCIPLED_ALPHA: f32 = 2e-3;
+
+fn schlick(f0: Color, f90: f32, cos_theta: f32) -> Color {
+  let m = (1.0 - cos_theta).clamp(0.0, 1.0);
+  let m5 = m * m * m * m * m;
+  f0 + (Color::splat(f90) - f0) * m5
+}
+
+fn max_channel(c: Color) -> f32 {
+  c.r.max(c.g).max(c.b)
+}
+
+/// Layered uber material with the parameters of glTF's metallic-roughness model. A diffuse and
+/// sheen base, dielectric specular reflection and tinted transmission are mixed with metallic
+/// reflection, and a clearcoat is layered on top. Roughness maps to the microfacet alpha as
+/// `alpha = roughness^2`, as in glTF.
+pub struct Principled {
+  base_color: Color,
+  metallic: f32,
+  specular: f32,
+  specular_tint: Color,
+  sheen: Color,
+  sheen_alpha: f32,
+  clearcoat: f32,
+  transmission: f32,
+  distribution: TrowbridgeReitz,
+  clearcoat_distribution: TrowbridgeReitz,
+  /// Rough dielectric interface used by the transmission lobe.
+  interface: Dielectric,
+}
+
+impl Principled {
+  pub fn new(params: &prefabs::Principled<Color, f32>) -> Self {
+    let alpha = |roughness: f32| (roughness * roughness).max(MIN_PRINCIPLED_ALPHA);
+    let distribution = TrowbridgeReitz::new(alpha(params.roughness), alpha(params.roughness));
+    Self {
+      base_color: params.base_color,
+      metallic: params.metallic.clamp(0.0, 1.0),
+      specular: params.specular.clamp(0.0, 1.0),
+      specular_tint: params.specular_tint,
+      sheen: params.sheen,
+      sheen_alpha: (params.sheen_roughness * params.sheen_roughness).max(0.07),
+      clearcoat: params.clearcoat.clamp(0.0, 1.0),
+      transmission: params.transmission.clamp(0.0, 1.0),
+      distribution,
+      clearcoat_distribution: TrowbridgeReitz::new(
+        alpha(params.clearcoat_roughness),
+        alpha(params.clearcoat_roughness),
+      ),
+      // the interface has no sharp limit, so that its lobes mix with the others
+      interface: Dielectric {
+        eta: params.ior.max(1.001),
+        distribution,
+      },
+    }
+  }
+
+  /// Fresnel reflectance of the non-metallic specular layer.
+  fn dielectric_fresnel(&self, cos_theta: f32) -> Color {
+    let eta = self.interface.eta;
+    let f0 = ((eta - 1.0) / (eta + 1.0)).powi(2);
+    let f0 = self.specular_tint * f0;
+    let f0 = Color::new(f0.r.min(1.0), f0.g.min(1.0), f0.b.min(1.0)) * self.specular;
+    schlick(f0, self.specular, cos_theta)
+  }
+
+  fn clearcoat_fresnel(&self, cos_theta: f32) -> f32 {
+    schlick(Color::splat(0.04), 1.0, cos_theta).r
+  }
+
+  /// Charlie sheen distribution with the visibility term of Neubelt and Pettineo.
+  fn sheen_term(&self, wo: Vec3A, wi: Vec3A, wm: Vec3A) -> Color {
+    let inv_alpha = 1.0 / self.sheen_alpha;
+    let sin2_m = (1.0 - wm.z * wm.z).max(0.0);
+    let d = (2.0 + inv_alpha) * sin2_m.powf(inv_alpha * 0.5) / (2.0 * PI);
+    let (cos_o, cos_i) = (wo.z, wi.z);
+    let v = 1.0 / (4.0 * (cos_i + cos_o - cos_i * cos_o));
+    self.sheen * (d * v)
+  }
+
+  fn base_weight(&self) -> f32 {
+    (1.0 - self.metallic) * (1.0 - self.transmission)
+  }
+
+  /// Probability of sampling the diffuse, specular, transmission and clearcoat lobes, roughly
+  /// proportional to how much light each one reflects towards `wo`.
+  fn lobe_probabilities(&self, wo: Vec3A) -> [f32; 4] {
+    let transmission = (1.0 - self.metallic) * self.transmission;
+    let mut weights = [0.0, 0.0, transmission, 0.0];
+    let cos_o = cos_theta(wo);
+    // only the transmission lobe reaches below the surface
+    if cos_o > 0.0 {
+      let fresnel = self.dielectric_fresnel(cos_o);
+      let metal = schlick(self.base_color, 1.0, cos_o);
+      weights[0] = self.base_weight()
+        * ((1.0 - max_channel(fresnel)) * self.base_color.luminance() + max_channel(self.sheen));
+      weights[1] = (fresnel * self.base_weight() + metal * self.metallic).luminance();
+      weights[3] = self.clearcoat * self.clearcoat_fresnel(cos_o);
+    }
+    let total: f32 = weights.iter().sum();
+    if total > 0.0 {
+      weights.map(|w| w / total)
+    } else {
+      [0.0; 4]
+    }
+  }
+
+  fn eval_local(&self, wo: Vec3A, wi: Vec3A, pdf: &mut f32) -> Color {
+    *pdf = 0.0;
+    let probabilities = self.lobe_probabilities(wo);
+    let (cos_o, cos_i) = (cos_theta(wo), cos_theta(wi));
+    let mut f = Color::BLACK;
+    let mut coat = Color::BLACK;
+
+    if cos_o > 0.0 && cos_i > 0.0 {
+      let wm = (wo + wi).normalize();
+      let cos_m = wo.dot(wm);
+      let fresnel = self.dielectric_fresnel(cos_m);
+
+      // diffuse and sheen, from whatever the specular layer lets through
+      let diffuse = self.base_color * (Color::WHITE - fresnel) / PI;
+      f = f + (diffuse + self.sheen_term(wo, wi, wm)) * self.base_weight();
+      *pdf += probabilities[0] * cos_i / PI;
+
+      let specular =
+        fresnel * self.base_weight() + schlick(self.base_color, 1.0, cos_m) * self.metallic;
+      let microfacet =
+        self.distribution.d(wm) * self.distribution.g(wo, wi) / (4.0 * cos_i * cos_o);
+      f = f + specular * microfacet;
+      *pdf += probabilities[1] * self.distribution.pdf(wo, wm) / (4.0 * cos_m);
+
+      if self.clearcoat > 0.0 {
+        let d = &self.clearcoat_distribution;
+        let microfacet = d.d(wm) * d.g(wo, wi) / (4.0 * cos_i * cos_o);
+        coat = Color::splat(self.clearcoat * self.clearcoat_fresnel(cos_m) * microfacet);
+        *pdf += probabilities[3] * d.pdf(wo, wm) / (4.0 * cos_m);
+      }
+    }
+
+    if probabilities[2] > 0.0 {
+      let mut interface_pdf = 0.0;
+      let interface = self.interface.eval_local(wo, wi, &mut interface_pdf);
+      // light crossing the surface is tinted by the base color
+      let tint = if same_hemisphere(wo, wi) {
+        Color::WHITE
+      } else {
+        self.base_color
+      };
+      f = f + interface * tint * ((1.0 - self.metallic) * self.transmission);
+      *pdf += probabilities[2] * interface_pdf;
+    }
+
+    if cos_o > 0.0 {
+      // the clearcoat reflects some light before it reaches the base
+      f = f * (1.0 - self.clearcoat * self.clearcoat_fresnel(cos_o)) + coat;
+    }
+    f
+  }
+
+  fn sample_local(
+    &self,
+    wo: Vec3A,
+    wi: &mut Vec3A,
+    pdf: &mut f32,
+    lobe: f32,
+    u: &glam::Vec2,
+  ) -> Color {
+    *pdf = 0.0;
+    let probabilities = self.lobe_probabilities(wo);
+    // pick a lobe and stretch the remaining part of `lobe` back over [0, 1)
+    let mut start = 0.0;
+    let mut chosen = None;
+    for (index, p) in probabilities.iter().enumerate() {
+      if *p > 0.0 && lobe < start + p {
+        chosen = Some((index, ((lobe - start) / p).min(1.0 - f32::EPSILON)));
+        break;
+      }
+      start += p;
+    }
+    let (index, remapped) = match chosen {
+      Some(chosen) => chosen,
+      None => return Color::BLACK,
+    };
+    let sampled = match index {
+      0 => cosine_sample_hemisphere(u),
+      1 => reflect(wo, self.distribution.sample_wm(wo, u)),
+      2 => {
+        let mut interface_pdf = 0.0;
+        let mut wt = Vec3A::ZERO;
+        self
+          .interface
+          .sample_local(wo, &mut wt, &mut interface_pdf, remapped, u);
+        if interface_pdf == 0.0 {
+          return Color::BLACK;
+        }
+        wt
+      }
+      _ => reflect(wo, self.clearcoat_distribution.sample_wm(wo, u)),
+    };
+    if cos_theta(sampled) == 0.0 || (index != 2 && !same_hemisphere(wo, sampled)) {
+      return Color::BLACK;
+    }
+    *wi = sampled;
+    self.eval_local(wo, sampled, pdf)
+  }
+}
+
+impl BSDF for Principled {
+  fn eval(&self, hit: &Hit, wo: &Vec3A, wi: &Vec3A, pdf: &mut f32) -> Color {
+    self.eval_local(hit.world_to_local(*wo), hit.world_to_local(*wi), pdf)
+  }
+
+  fn sample(
+    &self,
+    hit: &Hit,
+    wo: &Vec3A,
+    wi: &mut Vec3A,
+    pdf: &mut f32,
+    lobe: f32,
+    sample: &glam::Vec2,
+  ) -> Color {
+    let mut local_wi = Vec3A::ZERO;
+    let f = self.sample_local(hit.world_to_local(*wo), &mut local_wi, pdf, lobe, sample);
+    *wi = hit.local_to_world(local_wi);
+    f
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{math::uniform_sample_sphere, raytrace::rng::Pcg32};
+  use glam::Vec2;
+
+  fn assert_close(a: f32, b: f32, tolerance: f32) {
+    assert!(
+      (a - b).abs() <= tolerance * b.abs().max(1.0),
+      "{a} is not close to {b}"
+    );
+  }
+
+  fn uniform_2d(rng: &mut Pcg32) -> Vec2 {
+    Vec2::new(rng.uniform_f32(), rng.uniform_f32())
+  }
+
+  #[test]
+  fn dielectric_fresnel_matches_closed_forms() {
+    let eta = 1.5f32;
+    let normal = ((eta - 1.0) / (eta + 1.0)).powi(2);
+    assert_close(fresnel_dielectric(1.0, eta), normal, 1e-6);
+    assert_close(fresnel_dielectric(-1.0, eta), normal, 1e-6);
+    assert_close(fresnel_dielectric(0.0, eta), 1.0, 1e-6);
+
+    // the parallel polarization is fully transmitted at Brewster's angle
+    let brewster = eta.atan().cos();
+    let perpendicular = (1.0 - eta * eta) / (1.0 + eta * eta);
+    assert_close(
+      fresnel_dielectric(brewster, eta),
+      perpendicular * perpendicular / 2.0,
+      1e-5,
+    );
+
+    // light is totally reflected inside past the critical angle, and reciprocal below it
+    let critical = (1.0 / eta).asin();
+    assert_eq!(fresnel_dielectric(-(critical + 0.01).cos(), eta), 1.0);
+    for cos_i in [0.2f32, 0.5, 0.9] {
+      let cos_t = (1.0 - (1.0 - cos_i * cos_i) / (eta * eta)).sqrt();
+      assert_close(
+        fresnel_dielectric(cos_i, eta),
+        fresnel_dielectric(-cos_t, eta),
+        1e-5,
+      );
+    }
+  }
+
+  #[test]
+  fn smooth_dielectric_picks_lobes_by_fresnel() {
+    let dielectric = Dielectric::new(1.5, 0.0);
+    let wo = Vec3A::new(0.6, 0.0, 0.8);
+    let r = fresnel_dielectric(wo.z, 1.5);
+    let (mut wi, mut pdf) = (Vec3A::ZERO, 0.0);
+    dielectric.sample_local(wo, &mut wi, &mut pdf, r * 0.5, &Vec2::ZERO);
+    assert_eq!(wi, Vec3A::new(-0.6, 0.0, 0.8));
+    assert_close(pdf, r, 1e-6);
+    dielectric.sample_local(wo, &mut wi, &mut pdf, r + 0.01, &Vec2::ZERO);
+    assert!(wi.z < 0.0);
+    assert_close(pdf, 1.0 - r, 1e-6);
+    // Snell's law
+    assert_close(wi.truncate().length() * 1.5, 0.6, 1e-5);
+  }
+
+  #[test]
+  fn rough_dielectric_sample_matches_eval() {
+    let mut rng = Pcg32::default();
+    for eta in [1.5, 1.0 / 1.33] {
+      let dielectric = Dielectric::new(eta, 0.4);
+      for wo in [
+        Vec3A::new(0.3, 0.1, 0.95),
+        Vec3A::new(-0.8, 0.3, 0.2),
+        Vec3A::new(0.5, -0.2, -0.7),
+      ] {
+        let wo = wo.normalize();
+        for _ in 0..1000 {
+          let (mut wi, mut pdf) = (Vec3A::ZERO, 0.0);
+          let lobe = rng.uniform_f32();
+          let f = dielectric.sample_local(wo, &mut wi, &mut pdf, lobe, &uniform_2d(&mut rng));
+          if pdf == 0.0 {
+            continue;
+          }
+          let mut eval_pdf = 0.0;
+          let eval_f = dielectric.eval_local(wo, wi, &mut eval_pdf);
+          assert_close(eval_pdf, pdf, 1e-4);
+          assert_close(eval_f.r, f.r, 1e-4);
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn rough_dielectric_pdf_integrates_to_sampled_fraction() {
+    // the sample pdf integrated over the sphere is the chance of sampling a valid direction
+    let mut rng = Pcg32::default();
+    let dielectric = Dielectric::new(1.5, 0.6);
+    let wo = Vec3A::new(0.5, 0.0, 0.8).normalize();
+    let n = 1_000_000;
+    let mut valid = 0;
+    let mut integral = 0.0;
+    for _ in 0..n {
+      let (mut wi, mut pdf) = (Vec3A::ZERO, 0.0);
+      let lobe = rng.uniform_f32();
+      dielectric.sample_local(wo, &mut wi, &mut pdf, lobe, &uniform_2d(&mut rng));
+      if pdf > 0.0 {
+        valid += 1;
+      }
+      let mut eval_pdf = 0.0;
+      dielectric.eval_local(
+        wo,
+        uniform_sample_sphere(&uniform_2d(&mut rng)),
+        &mut eval_pdf,
+      );
+      integral += eval_pdf as f64 * 4.0 * std::f64::consts::PI;
+    }
+    let fraction = valid as f32 / n as f32;
+    assert_close((integral / n as f64) as f32, fraction, 0.02);
   }
 }
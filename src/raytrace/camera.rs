@@ -1,9 +1,18 @@
 use glam::{Affine3A, Mat3, Mat4, Vec2, Vec3, Vec3A};
 
-use crate::math::Ray;
+use crate::{
+  math::{concentric_sample_disk, Ray},
+  prefabs::{FisheyeMapping, Lens, LensElement, LensPrescription},
+};
+use std::f32::consts::PI;
 
 pub trait Camera: Sync + Send {
-  fn ray(&self, ndc: &Vec2) -> Ray;
+  /// Generate the ray through `ndc`, using `lens` in $[0,1)^2$ to pick a point on the aperture
+  /// for cameras that have one. Returns `None` where the projection does not cover the image.
+  ///
+  /// The ray comes with the weight that scales the radiance it carries, which is one for every
+  /// camera but the ones modeling how much light reaches the film.
+  fn ray(&self, ndc: &Vec2, lens: &Vec2) -> Option<(Ray, f32)>;
 }
 
 pub struct PinholeCamera {
@@ -34,7 +43,7 @@ impl PinholeCamera {
 }
 
 impl Camera for PinholeCamera {
-  fn ray(&self, ndc: &Vec2) -> Ray {
+  fn ray(&self, ndc: &Vec2, _lens: &Vec2) -> Option<(Ray, f32)> {
     let far_plane_hy = self.far * (self.fov_y * 0.5).tan();
     let far_plane_hx = self.aspect * far_plane_hy;
     let direction = Vec3A::new(
@@ -44,6 +53,589 @@ impl Camera for PinholeCamera {
     ).normalize();
     let origin = self.world_to_view.translation;
     let direction = self.world_to_view.transform_vector3a(direction);
-    Ray::new(origin, direction)
+    Some((Ray::new(origin, direction), 1.0))
+  }
+}
+
+/// Uniformly sample a regular polygon with `blades` corners inscribed in the unit circle.
+fn sample_polygon(blades: u32, rotation: f32, u: &Vec2) -> Vec2 {
+  // pick one of the triangles fanning out from the center, then reuse the sample inside it
+  let scaled = u.x * blades as f32;
+  let blade = (scaled as u32).min(blades - 1);
+  let u0 = scaled - blade as f32;
+  let angle = |i: u32| rotation + 2.0 * PI * i as f32 / blades as f32;
+  let (a, b) = (angle(blade), angle(blade + 1));
+  let v0 = Vec2::new(a.cos(), a.sin());
+  let v1 = Vec2::new(b.cos(), b.sin());
+  let s = u0.sqrt();
+  v0 * (s * (1.0 - u.y)) + v1 * (s * u.y)
+}
+
+/// Perspective camera with a finite aperture, focusing on a plane at the lens focus distance.
+pub struct ThinLensCamera {
+  tan_half_fov_y: f32,
+  aspect: f32,
+  lens: Lens,
+  camera_to_world: Affine3A,
+}
+
+impl ThinLensCamera {
+  pub fn new(fov_y: f32, aspect: f32, lens: Lens, camera_to_world: Affine3A) -> Self {
+    Self {
+      tan_half_fov_y: (fov_y * 0.5).tan(),
+      aspect,
+      lens,
+      camera_to_world,
+    }
+  }
+
+  fn sample_aperture(&self, u: &Vec2) -> Vec2 {
+    let p = if self.lens.blades >= 3 {
+      sample_polygon(self.lens.blades, self.lens.blade_rotation, u)
+    } else {
+      concentric_sample_disk(u)
+    };
+    Vec2::new(p.x / self.lens.anamorphic_squeeze, p.y) * self.lens.aperture_radius
+  }
+}
+
+impl Camera for ThinLensCamera {
+  fn ray(&self, ndc: &Vec2, lens: &Vec2) -> Option<(Ray, f32)> {
+    // point on the plane of focus seen through the center of the lens
+    let pinhole = Vec3A::new(
+      ndc.x * self.aspect * self.tan_half_fov_y,
+      ndc.y * self.tan_half_fov_y,
+      -1.0,
+    );
+    let focus = pinhole * self.lens.focus_distance;
+    let aperture = self.sample_aperture(lens);
+    let origin = Vec3A::new(aperture.x, aperture.y, 0.0);
+    let direction = (focus - origin).normalize();
+    let ray = Ray::new(
+      self.camera_to_world.transform_point3a(origin),
+      self.camera_to_world.transform_vector3a(direction),
+    );
+    Some((ray, 1.0))
+  }
+}
+
+/// Parallel projection looking down -Z, with rays starting on the near plane.
+pub struct OrthographicCamera {
+  min: Vec2,
+  max: Vec2,
+  near: f32,
+  camera_to_world: Affine3A,
+}
+
+impl OrthographicCamera {
+  pub fn new(
+    left: f32,
+    right: f32,
+    bottom: f32,
+    top: f32,
+    near: f32,
+    camera_to_world: Affine3A,
+  ) -> Self {
+    Self {
+      min: Vec2::new(left, bottom),
+      max: Vec2::new(right, top),
+      near,
+      camera_to_world,
+    }
+  }
+}
+
+impl Camera for OrthographicCamera {
+  fn ray(&self, ndc: &Vec2, _lens: &Vec2) -> Option<(Ray, f32)> {
+    let p = self.min + (*ndc * 0.5 + 0.5) * (self.max - self.min);
+    let origin = Vec3A::new(p.x, p.y, -self.near);
+    let direction = self.camera_to_world.transform_vector3a(-Vec3A::Z);
+    let ray = Ray::new(
+      self.camera_to_world.transform_point3a(origin),
+      direction.normalize(),
+    );
+    Some((ray, 1.0))
+  }
+}
+
+/// Direction for a point of a latitude-longitude image, using the same layout as
+/// [`EnvironmentMap`](super::EnvironmentMap) so rendered panoramas can be used as environments.
+fn equirectangular_direction(ndc: &Vec2) -> Vec3A {
+  let phi = ndc.x * PI;
+  let theta = (1.0 - ndc.y) * 0.5 * PI;
+  Vec3A::new(
+    theta.sin() * phi.sin(),
+    theta.cos(),
+    -theta.sin() * phi.cos(),
+  )
+}
+
+/// Full sphere camera producing a latitude-longitude image, best rendered at a 2:1 aspect.
+pub struct EquirectangularCamera {
+  camera_to_world: Affine3A,
+}
+
+impl EquirectangularCamera {
+  pub fn new(camera_to_world: Affine3A) -> Self {
+    Self { camera_to_world }
+  }
+}
+
+impl Camera for EquirectangularCamera {
+  fn ray(&self, ndc: &Vec2, _lens: &Vec2) -> Option<(Ray, f32)> {
+    let direction = equirectangular_direction(ndc);
+    let direction = self.camera_to_world.transform_vector3a(direction);
+    let ray = Ray::new(self.camera_to_world.translation, direction.normalize());
+    Some((ray, 1.0))
+  }
+}
+
+/// Fisheye lens covering `field_of_view` across the largest circle that fits in the image.
+/// Pixels outside of that circle get no ray.
+pub struct FisheyeCamera {
+  half_fov: f32,
+  aspect: f32,
+  mapping: FisheyeMapping,
+  camera_to_world: Affine3A,
+}
+
+impl FisheyeCamera {
+  pub fn new(
+    field_of_view: f32,
+    aspect: f32,
+    mapping: FisheyeMapping,
+    camera_to_world: Affine3A,
+  ) -> Self {
+    Self {
+      half_fov: (field_of_view * 0.5).min(PI),
+      aspect,
+      mapping,
+      camera_to_world,
+    }
+  }
+}
+
+impl Camera for FisheyeCamera {
+  fn ray(&self, ndc: &Vec2, _lens: &Vec2) -> Option<(Ray, f32)> {
+    // fit the image circle to the shorter side
+    let p = if self.aspect >= 1.0 {
+      Vec2::new(ndc.x * self.aspect, ndc.y)
+    } else {
+      Vec2::new(ndc.x, ndc.y / self.aspect)
+    };
+    let r = p.length();
+    if r > 1.0 {
+      return None;
+    }
+    // angle from the optical axis
+    let theta = match self.mapping {
+      FisheyeMapping::Equidistant => r * self.half_fov,
+      FisheyeMapping::Equisolid => 2.0 * (r * (self.half_fov * 0.5).sin()).clamp(-1.0, 1.0).asin(),
+    };
+    let (sin_phi, cos_phi) = if r > 0.0 {
+      (p.y / r, p.x / r)
+    } else {
+      (0.0, 1.0)
+    };
+    let direction = Vec3A::new(theta.sin() * cos_phi, theta.sin() * sin_phi, -theta.cos());
+    let direction = self.camera_to_world.transform_vector3a(direction);
+    let ray = Ray::new(self.camera_to_world.translation, direction.normalize());
+    Some((ray, 1.0))
+  }
+}
+
+/// Omni-directional stereo panorama, with the left eye in the top half of the image and the
+/// right eye in the bottom half. Each ray starts from an eye on a circle of diameter
+/// `interpupillary_distance`, offset sideways from the horizontal viewing direction.
+pub struct OmniStereoCamera {
+  interpupillary_distance: f32,
+  camera_to_world: Affine3A,
+}
+
+impl OmniStereoCamera {
+  pub fn new(interpupillary_distance: f32, camera_to_world: Affine3A) -> Self {
+    Self {
+      interpupillary_distance,
+      camera_to_world,
+    }
+  }
+}
+
+impl Camera for OmniStereoCamera {
+  fn ray(&self, ndc: &Vec2, _lens: &Vec2) -> Option<(Ray, f32)> {
+    let (eye, y) = if ndc.y >= 0.0 {
+      (-1.0, ndc.y * 2.0 - 1.0)
+    } else {
+      (1.0, ndc.y * 2.0 + 1.0)
+    };
+    let direction = equirectangular_direction(&Vec2::new(ndc.x, y));
+    let phi = ndc.x * PI;
+    let right = Vec3A::new(phi.cos(), 0.0, phi.sin());
+    let origin = right * (eye * 0.5 * self.interpupillary_distance);
+    let direction = self.camera_to_world.transform_vector3a(direction);
+    let ray = Ray::new(
+      self.camera_to_world.transform_point3a(origin),
+      direction.normalize(),
+    );
+    Some((ray, 1.0))
+  }
+}
+
+fn refract(wi: Vec3A, n: Vec3A, eta: f32) -> Option<Vec3A> {
+  let cos_theta_i = n.dot(wi);
+  let sin2_theta_t = eta * eta * (1.0 - cos_theta_i * cos_theta_i).max(0.0);
+  if sin2_theta_t >= 1.0 {
+    return None;
+  }
+  let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+  Some(-wi * eta + n * (eta * cos_theta_i - cos_theta_t))
+}
+
+/// Intersect a ray with a spherical lens interface centered on the axis at `z_center`, returning
+/// the distance along the ray and the normal facing against the ray.
+fn intersect_spherical_element(radius: f32, z_center: f32, ray: &Ray) -> Option<(f32, Vec3A)> {
+  let o = ray.origin - Vec3A::new(0.0, 0.0, z_center);
+  let a = ray.direction.length_squared();
+  let b = 2.0 * ray.direction.dot(o);
+  let c = o.length_squared() - radius * radius;
+  let discriminant = b * b - 4.0 * a * c;
+  if discriminant < 0.0 {
+    return None;
+  }
+  let root = discriminant.sqrt();
+  let (t0, t1) = ((-b - root) / (2.0 * a), (-b + root) / (2.0 * a));
+  // only one of the two intersections lies on the part of the sphere that forms the interface
+  let use_closer = (ray.direction.z > 0.0) ^ (radius < 0.0);
+  let t = if use_closer { t0.min(t1) } else { t0.max(t1) };
+  if t < 0.0 {
+    return None;
+  }
+  let n = (o + ray.direction * t).normalize();
+  let n = if n.dot(-ray.direction) < 0.0 { -n } else { n };
+  Some((t, n))
+}
+
+/// Number of radial film intervals with their own exit pupil bounds.
+const EXIT_PUPIL_INTERVALS: usize = 64;
+/// Grid resolution over the rear element when searching for the exit pupil of an interval.
+const EXIT_PUPIL_GRID: usize = 64;
+
+/// Camera tracing rays from the film through a sequence of spherical lens interfaces, which
+/// gives the distortion, vignetting and focus breathing of the actual lens design.
+///
+/// Lens space is camera space: the film lies in the `z = 0` plane and the lens extends
+/// towards -Z. Rays are aimed at the exit pupil, the part of the rear element that light from the
+/// scene reaches a film point through, and weighted by the irradiance they carry onto the film,
+/// so images come out darker than with the other cameras. Rays that the lens housing or aperture
+/// stop block are not generated, so pixels darken towards the image corners.
+pub struct RealisticCamera {
+  elements: Vec<LensElement>,
+  film_extent: Vec2,
+  /// Bounds of the exit pupil on the rear element plane for film points on the +X axis, per
+  /// radial interval from the center of the film to its corner. `None` where no light gets
+  /// through.
+  exit_pupil_bounds: Vec<Option<(Vec2, Vec2)>>,
+  camera_to_world: Affine3A,
+}
+
+impl RealisticCamera {
+  pub fn new(
+    prescription: &LensPrescription,
+    film_diagonal: f32,
+    aspect: f32,
+    focus_distance: f32,
+    camera_to_world: Affine3A,
+  ) -> Self {
+    let film_height = film_diagonal / (1.0 + aspect * aspect).sqrt();
+    let mut camera = Self {
+      elements: prescription.elements.clone(),
+      film_extent: Vec2::new(film_height * aspect, film_height) * 0.5,
+      exit_pupil_bounds: Vec::new(),
+      camera_to_world,
+    };
+    let back_focus = camera.focus_thick_lens(focus_distance);
+    if let Some(last) = camera.elements.last_mut() {
+      last.thickness = back_focus;
+    }
+    // the exit pupil depends on the distance between the film and the lens
+    let film_radius = camera.film_extent.length();
+    camera.exit_pupil_bounds = (0..EXIT_PUPIL_INTERVALS)
+      .map(|i| {
+        let r0 = i as f32 / EXIT_PUPIL_INTERVALS as f32 * film_radius;
+        let r1 = (i + 1) as f32 / EXIT_PUPIL_INTERVALS as f32 * film_radius;
+        camera.bound_exit_pupil(r0, r1)
+      })
+      .collect();
+    camera
+  }
+
+  fn rear_z(&self) -> f32 {
+    self.elements.last().map_or(0.0, |e| e.thickness)
+  }
+
+  fn front_z(&self) -> f32 {
+    self.elements.iter().map(|e| e.thickness).sum()
+  }
+
+  fn trace_from_film(&self, ray: &Ray) -> Option<Ray> {
+    let mut ray = *ray;
+    let mut element_z = 0.0;
+    for (i, element) in self.elements.iter().enumerate().rev() {
+      element_z -= element.thickness;
+      let is_stop = element.curvature_radius == 0.0;
+      let (t, n) = if is_stop {
+        ((element_z - ray.origin.z) / ray.direction.z, Vec3A::ZERO)
+      } else {
+        let z_center = element_z + element.curvature_radius;
+        intersect_spherical_element(element.curvature_radius, z_center, &ray)?
+      };
+      if t.is_nan() || t < 0.0 {
+        return None;
+      }
+      let p = ray.origin + ray.direction * t;
+      if p.x * p.x + p.y * p.y > element.aperture_radius * element.aperture_radius {
+        return None;
+      }
+      ray.origin = p;
+      if !is_stop {
+        let eta_i = element.eta;
+        let eta_t = if i > 0 { self.elements[i - 1].eta } else { 1.0 };
+        ray.direction = refract(-ray.direction.normalize(), n, eta_i / eta_t)?;
+      }
+    }
+    Some(ray)
+  }
+
+  fn trace_from_scene(&self, ray: &Ray) -> Option<Ray> {
+    let mut ray = *ray;
+    let mut element_z = -self.front_z();
+    for (i, element) in self.elements.iter().enumerate() {
+      let is_stop = element.curvature_radius == 0.0;
+      let (t, n) = if is_stop {
+        ((element_z - ray.origin.z) / ray.direction.z, Vec3A::ZERO)
+      } else {
+        let z_center = element_z + element.curvature_radius;
+        intersect_spherical_element(element.curvature_radius, z_center, &ray)?
+      };
+      if t.is_nan() || t < 0.0 {
+        return None;
+      }
+      let p = ray.origin + ray.direction * t;
+      if p.x * p.x + p.y * p.y > element.aperture_radius * element.aperture_radius {
+        return None;
+      }
+      ray.origin = p;
+      if !is_stop {
+        let eta_i = if i > 0 { self.elements[i - 1].eta } else { 1.0 };
+        let eta_t = element.eta;
+        ray.direction = refract(-ray.direction.normalize(), n, eta_i / eta_t)?;
+      }
+      element_z += element.thickness;
+    }
+    Some(ray)
+  }
+
+  /// Principal plane and focal point along the axis for a ray entering parallel to the axis.
+  fn cardinal_points(ray_in: &Ray, ray_out: &Ray) -> (f32, f32) {
+    let tf = -ray_out.origin.x / ray_out.direction.x;
+    let focal_z = ray_out.origin.z + ray_out.direction.z * tf;
+    let tp = (ray_in.origin.x - ray_out.origin.x) / ray_out.direction.x;
+    let principal_z = ray_out.origin.z + ray_out.direction.z * tp;
+    (principal_z, focal_z)
+  }
+
+  /// Distance from the last interface to the film that brings `focus_distance` into focus,
+  /// using the thick lens approximation of the system.
+  fn focus_thick_lens(&self, focus_distance: f32) -> f32 {
+    let height = 0.001 * self.film_extent.length();
+    let scene_ray = Ray::new(Vec3A::new(height, 0.0, -self.front_z() - 1.0), Vec3A::Z);
+    let film_ray = Ray::new(Vec3A::new(height, 0.0, 1.0), -Vec3A::Z);
+    let (image, object) = match (
+      self.trace_from_scene(&scene_ray),
+      self.trace_from_film(&film_ray),
+    ) {
+      (Some(image), Some(object)) => (image, object),
+      _ => return self.rear_z(),
+    };
+    let (p_image, f_image) = Self::cardinal_points(&scene_ray, &image);
+    let (p_object, _) = Self::cardinal_points(&film_ray, &object);
+    let f = f_image - p_image;
+    let z = -focus_distance;
+    let c = (p_object - z - p_image) * (p_object - z - 4.0 * f - p_image);
+    if c < 0.0 {
+      return self.rear_z();
+    }
+    let delta = 0.5 * (p_object - z + p_image - c.sqrt());
+    self.rear_z() + delta
+  }
+
+  /// Bound the points of the rear element plane that rays from film points between `r0` and
+  /// `r1` along the +X axis pass through the whole lens from, by tracing a grid of rays over the
+  /// rear element from both ends of the interval.
+  fn bound_exit_pupil(&self, r0: f32, r1: f32) -> Option<(Vec2, Vec2)> {
+    // the rear element is curved, so rays can cross its plane outside of its radius
+    let extent = 1.5 * self.elements.last()?.aperture_radius;
+    let spacing = 2.0 * extent / EXIT_PUPIL_GRID as f32;
+    let mut bounds: Option<(Vec2, Vec2)> = None;
+    for film in [Vec3A::new(r0, 0.0, 0.0), Vec3A::new(r1, 0.0, 0.0)] {
+      for i in 0..EXIT_PUPIL_GRID * EXIT_PUPIL_GRID {
+        let (gx, gy) = (i % EXIT_PUPIL_GRID, i / EXIT_PUPIL_GRID);
+        let rear = Vec2::new(gx as f32 + 0.5, gy as f32 + 0.5) * spacing - extent;
+        let target = Vec3A::new(rear.x, rear.y, -self.rear_z());
+        let ray = Ray::new(film, (target - film).normalize());
+        if self.trace_from_film(&ray).is_some() {
+          bounds = Some(match bounds {
+            Some((min, max)) => (min.min(rear), max.max(rear)),
+            None => (rear, rear),
+          });
+        }
+      }
+    }
+    // grow the bounds by a grid cell, since the pupil may extend past the last point that hit
+    bounds.map(|(min, max)| (min - spacing, max + spacing))
+  }
+
+  /// Pick a point on the rear element plane inside the exit pupil of the film point `film`,
+  /// returning it along with the area of the bounds it was sampled from.
+  fn sample_exit_pupil(&self, film: Vec2, u: &Vec2) -> Option<(Vec3A, f32)> {
+    let r = film.length();
+    let interval = (r / self.film_extent.length() * EXIT_PUPIL_INTERVALS as f32) as usize;
+    let (min, max) = self.exit_pupil_bounds[interval.min(EXIT_PUPIL_INTERVALS - 1)]?;
+    let p = min + (max - min) * *u;
+    let area = (max.x - min.x) * (max.y - min.y);
+    // the bounds were found for film points on the +X axis, so rotate them to the film point
+    let (sin_phi, cos_phi) = if r > 0.0 {
+      (film.y / r, film.x / r)
+    } else {
+      (0.0, 1.0)
+    };
+    let rear = Vec3A::new(
+      cos_phi * p.x - sin_phi * p.y,
+      sin_phi * p.x + cos_phi * p.y,
+      -self.rear_z(),
+    );
+    Some((rear, area))
+  }
+}
+
+impl Camera for RealisticCamera {
+  fn ray(&self, ndc: &Vec2, lens: &Vec2) -> Option<(Ray, f32)> {
+    // the lens forms an inverted image on the film
+    let film = -*ndc * self.film_extent;
+    let (rear, area) = self.sample_exit_pupil(film, lens)?;
+    let film = Vec3A::new(film.x, film.y, 0.0);
+    let direction = (rear - film).normalize();
+    let ray = self.trace_from_film(&Ray::new(film, direction))?;
+    // irradiance on the film falls off with cos^4 of the angle to the axis, scaled by the solid
+    // angle the sampled pupil area covers
+    let cos_theta = -direction.z;
+    let weight = cos_theta.powi(4) * area / (self.rear_z() * self.rear_z());
+    let direction = self.camera_to_world.transform_vector3a(ray.direction);
+    let ray = Ray::new(
+      self.camera_to_world.transform_point3a(ray.origin),
+      direction.normalize(),
+    );
+    Some((ray, weight))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Double Gauss 50mm f/2 design from pbrt-v3's lens collection.
+  const DOUBLE_GAUSS: &str = "
+    # radius thickness eta aperture
+    29.475   3.76   1.67   25.2
+    84.83    0.12   1      25.2
+    19.275   4.025  1.67   23
+    40.77    3.275  1.699  23
+    12.75    5.705  1      18
+    0        4.5    0      17.1
+    -14.495  1.18   1.603  17
+    40.77    6.065  1.658  20
+    -20.385  0.19   1      20
+    437.065  3.22   1.717  20
+    -39.73   5      1      20
+  ";
+
+  fn camera(focus_distance: f32) -> RealisticCamera {
+    let prescription = LensPrescription::parse(DOUBLE_GAUSS).unwrap();
+    RealisticCamera::new(
+      &prescription,
+      0.035,
+      1.5,
+      focus_distance,
+      Affine3A::IDENTITY,
+    )
+  }
+
+  #[test]
+  fn focus_thick_lens() {
+    let mut previous = 0.0;
+    for focus_distance in [10.0, 2.0, 0.5] {
+      let camera = camera(focus_distance);
+      // the lens moves away from the film to focus closer
+      assert!(camera.rear_z() > previous);
+      previous = camera.rear_z();
+      // paraxial rays from the point in focus on the axis meet again on the film
+      let origin = Vec3A::new(0.0, 0.0, -focus_distance);
+      let target = Vec3A::new(0.0005, 0.0, -camera.front_z());
+      let ray = camera.trace_from_scene(&Ray::new(origin, (target - origin).normalize()));
+      let ray = ray.unwrap();
+      let z = ray.origin.z - ray.origin.x / ray.direction.x * ray.direction.z;
+      assert!(z.abs() < 1e-4, "focused at {z} for {focus_distance}");
+    }
+  }
+
+  #[test]
+  fn exit_pupil_bounds_cover_the_lens() {
+    let camera = camera(2.0);
+    let extent = 1.5 * camera.elements.last().unwrap().aperture_radius;
+    let n = 64;
+    for r in [0.0, 0.3, 0.7, 1.0] {
+      let film = Vec2::new(-0.8, 0.6) * r * camera.film_extent.length();
+      let film3 = Vec3A::new(film.x, film.y, 0.0);
+      // corners of the rotated bounds
+      let (area, corners) = {
+        let sample = |x, y| camera.sample_exit_pupil(film, &Vec2::new(x, y)).unwrap();
+        let corners = [sample(0.0, 0.0), sample(1.0, 0.0), sample(0.0, 1.0)];
+        (corners[0].1, corners.map(|(p, _)| Vec2::new(p.x, p.y)))
+      };
+      let (edge_x, edge_y) = (corners[1] - corners[0], corners[2] - corners[0]);
+      assert!((edge_x.length() * edge_y.length() - area).abs() <= 1e-3 * area);
+      // every point of the rear element that light gets through lies inside the rotated bounds
+      for i in 0..n * n {
+        let rear = (Vec2::new((i % n) as f32, (i / n) as f32) + 0.5) / n as f32 * 2.0 - 1.0;
+        let rear = Vec3A::new(rear.x, rear.y, 0.0) * extent - Vec3A::Z * camera.rear_z();
+        let ray = Ray::new(film3, (rear - film3).normalize());
+        if camera.trace_from_film(&ray).is_some() {
+          let d = Vec2::new(rear.x, rear.y) - corners[0];
+          let (x, y) = (
+            d.dot(edge_x) / edge_x.length_squared(),
+            d.dot(edge_y) / edge_y.length_squared(),
+          );
+          assert!((0.0..=1.0).contains(&x) && (0.0..=1.0).contains(&y));
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn natural_vignetting() {
+    let camera = camera(2.0);
+    let weight = |ndc: Vec2| {
+      let mut sum = 0.0;
+      for i in 0..256 {
+        let lens = Vec2::new((i % 16) as f32 + 0.5, (i / 16) as f32 + 0.5) / 16.0;
+        sum += camera.ray(&ndc, &lens).map_or(0.0, |(_, weight)| weight);
+      }
+      sum / 256.0
+    };
+    let center = weight(Vec2::ZERO);
+    let edge = weight(Vec2::new(0.7, 0.0));
+    let corner = weight(Vec2::new(0.95, 0.95));
+    assert!(center > 0.0);
+    assert!(center > edge && edge > corner, "{center} {edge} {corner}");
   }
 }
@@ -0,0 +1,186 @@
+use super::imageio::{Image, ImageError};
+use crate::math::{Color, Distribution2D};
+use glam::{Quat, Vec2, Vec3A};
+use std::{f32::consts::PI, path::Path, sync::Arc};
+
+/// Equirectangular (latitude-longitude) environment image, importance sampled by luminance.
+pub struct EnvironmentMap {
+  image: Image,
+  rotation: Quat,
+  intensity: f32,
+  distribution: Distribution2D,
+}
+
+impl EnvironmentMap {
+  pub fn new(image: Image) -> Self {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    // weight each row by its solid angle so the poles are not oversampled
+    let mut func = Vec::with_capacity(width * height);
+    for y in 0..height {
+      let sin_theta = (PI * (y as f32 + 0.5) / height as f32).sin();
+      for x in 0..width {
+        func.push(image.pixel(x as u32, y as u32).luminance() * sin_theta);
+      }
+    }
+    let distribution = Distribution2D::new(&func, width, height);
+    Self {
+      image,
+      rotation: Quat::IDENTITY,
+      intensity: 1.0,
+      distribution,
+    }
+  }
+
+  pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ImageError> {
+    Ok(Self::new(Image::load(path)?))
+  }
+
+  pub fn with_rotation(mut self, rotation: Quat) -> Self {
+    self.rotation = rotation;
+    self
+  }
+
+  pub fn with_intensity(mut self, intensity: f32) -> Self {
+    self.intensity = intensity;
+    self
+  }
+
+  fn direction_to_uv(&self, direction: Vec3A) -> Vec2 {
+    let d = (self.rotation.inverse() * direction).normalize();
+    let phi = d.x.atan2(-d.z);
+    let theta = d.y.clamp(-1.0, 1.0).acos();
+    Vec2::new(phi / (2.0 * PI) + 0.5, theta / PI)
+  }
+
+  fn uv_to_direction(&self, uv: Vec2) -> Vec3A {
+    let phi = (uv.x - 0.5) * 2.0 * PI;
+    let theta = uv.y * PI;
+    let d = Vec3A::new(
+      theta.sin() * phi.sin(),
+      theta.cos(),
+      -theta.sin() * phi.cos(),
+    );
+    self.rotation * d
+  }
+
+  fn lookup(&self, uv: Vec2) -> Color {
+    let x = ((uv.x * self.image.width() as f32) as u32).min(self.image.width() - 1);
+    let y = ((uv.y * self.image.height() as f32) as u32).min(self.image.height() - 1);
+    self.image.pixel(x, y) * self.intensity
+  }
+
+  pub(super) fn radiance(&self, direction: Vec3A) -> Color {
+    self.lookup(self.direction_to_uv(direction))
+  }
+
+  /// Sample an incident direction proportional to the map's luminance. Returns the direction,
+  /// the radiance arriving from it and its solid angle density.
+  pub(super) fn sample(&self, u: &Vec2) -> Option<(Vec3A, Color, f32)> {
+    let (uv, map_pdf) = self.distribution.sample_continuous(u);
+    if map_pdf == 0.0 {
+      return None;
+    }
+    let sin_theta = (uv.y * PI).sin();
+    if sin_theta == 0.0 {
+      return None;
+    }
+    let pdf = map_pdf / (2.0 * PI * PI * sin_theta);
+    Some((self.uv_to_direction(uv), self.lookup(uv), pdf))
+  }
+
+  pub(super) fn pdf(&self, direction: Vec3A) -> f32 {
+    let uv = self.direction_to_uv(direction);
+    let sin_theta = (uv.y * PI).sin();
+    if sin_theta == 0.0 {
+      return 0.0;
+    }
+    self.distribution.pdf(&uv) / (2.0 * PI * PI * sin_theta)
+  }
+}
+
+/// Radiance arriving from directions that leave the scene.
+#[derive(Clone)]
+pub enum Sky {
+  Uniform(Color),
+  /// Blend between `bottom` looking straight down and `top` looking straight up.
+  Gradient {
+    bottom: Color,
+    top: Color,
+  },
+  Environment(Arc<EnvironmentMap>),
+}
+
+impl Default for Sky {
+  fn default() -> Self {
+    Sky::Gradient {
+      bottom: Color::new(1.0, 1.0, 1.0),
+      top: Color::new(0.5, 0.7, 1.0),
+    }
+  }
+}
+
+impl Sky {
+  pub(super) fn radiance(&self, direction: Vec3A) -> Color {
+    match self {
+      Sky::Uniform(color) => *color,
+      Sky::Gradient { bottom, top } => {
+        let t = 0.5 * (direction.y + 1.0);
+        *bottom * (1.0 - t) + *top * t
+      }
+      Sky::Environment(map) => map.radiance(direction),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{math::uniform_sample_sphere, raytrace::rng::Pcg32};
+
+  /// Dim map with a gradient and one bright texel, rotated so the poles are off the y axis.
+  fn map() -> EnvironmentMap {
+    let (width, height) = (16, 8);
+    let pixels = (0..width * height)
+      .map(|i| match i {
+        37 => Color::splat(50.0),
+        _ => Color::splat(0.1 + (i % width) as f32 / width as f32),
+      })
+      .collect();
+    EnvironmentMap::new(Image::new(width, height, pixels)).with_rotation(Quat::from_rotation_x(0.4))
+  }
+
+  #[test]
+  fn sampled_pdf_matches_pdf() {
+    let map = map();
+    let mut rng = Pcg32::default();
+    for _ in 0..10_000 {
+      let u = Vec2::new(rng.uniform_f32(), rng.uniform_f32());
+      let (wi, li, pdf) = match map.sample(&u) {
+        Some(sample) => sample,
+        None => continue,
+      };
+      // directions near the poles lose some precision on their way back to the map
+      let expected = map.pdf(wi);
+      assert!(
+        (pdf - expected).abs() <= 1e-2 * expected,
+        "{pdf} != {expected}"
+      );
+      assert_eq!(li, map.radiance(wi));
+    }
+  }
+
+  #[test]
+  fn pdf_integrates_to_one() {
+    let map = map();
+    let mut rng = Pcg32::default();
+    let n = 1_000_000;
+    let integral = (0..n)
+      .map(|_| {
+        let u = Vec2::new(rng.uniform_f32(), rng.uniform_f32());
+        map.pdf(uniform_sample_sphere(&u)) as f64 * 4.0 * std::f64::consts::PI
+      })
+      .sum::<f64>()
+      / n as f64;
+    assert!((integral - 1.0).abs() < 0.02, "{integral}");
+  }
+}
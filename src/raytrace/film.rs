@@ -1,15 +1,124 @@
-use crate::math::{self, Color};
+use super::{
+  filter::Filter,
+  imageio::{self, Image, ImageError},
+  tonemap::DisplaySettings,
+  RenderSettings,
+};
+use crate::math::Color;
+use glam::Vec2;
+use std::{path::Path, thread, time::Duration};
 
+/// Describes how a film was rendered, for embedding into saved images.
+pub struct RenderMetadata {
+  pub samples_per_pixel: u32,
+  pub render_time: Duration,
+  pub settings: RenderSettings,
+}
+
+impl RenderMetadata {
+  fn text_entries(&self) -> Vec<(String, String)> {
+    vec![
+      ("Software".into(), "flux".into()),
+      ("SamplesPerPixel".into(), self.samples_per_pixel.to_string()),
+      (
+        "RenderTime".into(),
+        format!("{:.3}s", self.render_time.as_secs_f64()),
+      ),
+      ("RenderSettings".into(), format!("{:?}", self.settings)),
+    ]
+  }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Pixel {
+  rgb_sum: Color,
+  weight_sum: f32,
+}
+
+/// Radiance sample taken for a pixel during a pass, at `offset` inside the pixel.
+#[derive(Clone, Copy)]
+struct PixelSample {
+  color: Color,
+  offset: Vec2,
+}
+
+/// Rectangular region of the film, `[x0, x1) x [y0, y1)`.
+#[derive(Clone, Copy)]
+pub(super) struct TileBounds {
+  pub x0: u32,
+  pub y0: u32,
+  pub x1: u32,
+  pub y1: u32,
+}
+
+/// Split an image into tiles of at most `tile_size` pixels on each side, in scanline order.
+pub(super) fn tile_bounds(width: u32, height: u32, tile_size: u32) -> Vec<TileBounds> {
+  let tile_size = tile_size.max(1);
+  let mut tiles = Vec::new();
+  for y0 in (0..height).step_by(tile_size as usize) {
+    for x0 in (0..width).step_by(tile_size as usize) {
+      tiles.push(TileBounds {
+        x0,
+        y0,
+        x1: (x0 + tile_size).min(width),
+        y1: (y0 + tile_size).min(height),
+      });
+    }
+  }
+  tiles
+}
+
+/// Samples taken for one tile during a pass, merged into the film once the tile is done.
+pub(super) struct FilmTile {
+  bounds: TileBounds,
+  samples: Vec<Option<PixelSample>>,
+}
+
+impl FilmTile {
+  pub fn new(bounds: TileBounds) -> Self {
+    let size = (bounds.x1 - bounds.x0) * (bounds.y1 - bounds.y0);
+    Self {
+      bounds,
+      samples: vec![None; size as usize],
+    }
+  }
+
+  /// Record the sample of this pass for the pixel at film coordinates `(x, y)`, taken at
+  /// `offset` inside the pixel.
+  pub fn add_sample(&mut self, x: u32, y: u32, offset: Vec2, color: Color) {
+    let width = self.bounds.x1 - self.bounds.x0;
+    self.samples[((y - self.bounds.y0) * width + x - self.bounds.x0) as usize] =
+      Some(PixelSample { color, offset });
+  }
+}
+
+/// Linear radiance accumulation buffer together with an 8-bit display copy of it.
+///
+/// The renderer takes one sample per pixel and pass. Those are collected first and then splatted
+/// through the reconstruction filter all at once, with every pixel summing its neighbors in the
+/// same order, so the result does not depend on how the pass was split between threads.
+///
+/// Rows are stored bottom to top, as the viewer displays them, so pixel `(0, 0)` is the bottom
+/// left corner of the image. Saved images are written top to bottom.
 pub struct Film {
   dimension: (u32, u32),
-  data: Vec<[u8; 4]>,
+  pixels: Vec<Pixel>,
+  pass: Vec<Option<PixelSample>>,
+  display: Vec<[u8; 4]>,
+  display_settings: DisplaySettings,
+  /// Bumped by every `clear`, so that a render can tell the film was taken over by another one.
+  generation: u64,
 }
 
 impl Film {
   pub fn new(width: u32, height: u32) -> Self {
     Self {
       dimension: (width, height),
-      data: vec![[0; 4]; (width * height) as usize],
+      pixels: vec![Pixel::default(); (width * height) as usize],
+      pass: vec![None; (width * height) as usize],
+      display: vec![[0; 4]; (width * height) as usize],
+      display_settings: DisplaySettings::default(),
+      generation: 0,
     }
   }
 
@@ -29,25 +138,253 @@ impl Film {
     self.x_stride() * self.dimension.0 as usize
   }
 
+  /// The 8-bit RGBA display buffer, as of the last call to [`Film::update_display`].
   pub fn data(&self) -> &[u8] {
     unsafe {
       std::slice::from_raw_parts(
-        self.data.as_ptr() as *const u8,
-        self.data.len() * self.x_stride(),
+        self.display.as_ptr() as *const u8,
+        self.display.len() * self.x_stride(),
       )
     }
   }
 
+  /// The weighted average of all radiance accumulated into a pixel.
   pub fn pixel(&self, x: u32, y: u32) -> Color {
-    let data = self.data[(y * self.dimension.0 + x) as usize];
-    Color::new(
-      data[0] as f32 / 255.0,
-      data[1] as f32 / 255.0,
-      data[2] as f32 / 255.0,
-    )
+    let pixel = &self.pixels[(y * self.dimension.0 + x) as usize];
+    // filters with negative lobes can leave a pixel with no usable weight
+    if pixel.weight_sum <= 0.0 {
+      Color::BLACK
+    } else {
+      pixel.rgb_sum / pixel.weight_sum
+    }
+  }
+
+  pub fn add_sample(&mut self, x: u32, y: u32, color: Color, weight: f32) {
+    let pixel = &mut self.pixels[(y * self.dimension.0 + x) as usize];
+    pixel.rgb_sum = pixel.rgb_sum + color * weight;
+    pixel.weight_sum += weight;
+  }
+
+  pub(super) fn merge_tile(&mut self, tile: &FilmTile) {
+    let bounds = &tile.bounds;
+    let width = bounds.x1 - bounds.x0;
+    for y in bounds.y0..bounds.y1 {
+      let src = ((y - bounds.y0) * width) as usize;
+      let dst = (y * self.dimension.0 + bounds.x0) as usize;
+      self.pass[dst..dst + width as usize]
+        .copy_from_slice(&tile.samples[src..src + width as usize]);
+    }
+  }
+
+  /// Splat the samples of the current pass into the accumulated pixels, weighted by `filter`.
+  pub(super) fn splat_pass(&mut self, filter: &Filter, workers: usize) {
+    let (width, height) = self.dimension;
+    if width == 0 || height == 0 {
+      return;
+    }
+    let radius = filter.radius();
+    let reach = radius.ceil() as i32 + 1;
+    let pass = &self.pass;
+    let rows_per_worker = (height as usize).div_ceil(workers.max(1));
+    thread::scope(|scope| {
+      for (chunk, pixels) in self
+        .pixels
+        .chunks_mut(rows_per_worker * width as usize)
+        .enumerate()
+      {
+        scope.spawn(move || {
+          let y_start = (chunk * rows_per_worker) as i32;
+          for (i, pixel) in pixels.iter_mut().enumerate() {
+            let x = (i % width as usize) as i32;
+            let y = y_start + (i / width as usize) as i32;
+            let center = Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+            for sy in (y - reach).max(0)..(y + reach + 1).min(height as i32) {
+              for sx in (x - reach).max(0)..(x + reach + 1).min(width as i32) {
+                let sample = match &pass[(sy as u32 * width + sx as u32) as usize] {
+                  Some(sample) => sample,
+                  None => continue,
+                };
+                let position = Vec2::new(sx as f32, sy as f32) + sample.offset;
+                let weight = filter.evaluate(position - center);
+                if weight != 0.0 {
+                  pixel.rgb_sum = pixel.rgb_sum + sample.color * weight;
+                  pixel.weight_sum += weight;
+                }
+              }
+            }
+          }
+        });
+      }
+    });
+    self.pass.fill(None);
+  }
+
+  /// Reset the film for a new render, returning the generation that render writes into.
+  pub fn clear(&mut self) -> u64 {
+    self.pixels.fill(Pixel::default());
+    self.pass.fill(None);
+    self.display.fill([0; 4]);
+    self.generation += 1;
+    self.generation
+  }
+
+  pub fn generation(&self) -> u64 {
+    self.generation
+  }
+
+  /// Resolve the accumulated radiance into the 8-bit sRGB display buffer.
+  pub fn update_display(&mut self, settings: &DisplaySettings) {
+    self.display_settings = *settings;
+    self.display = self.resolve_display();
+  }
+
+  fn resolve_display(&self) -> Vec<[u8; 4]> {
+    let mut display = Vec::with_capacity(self.pixels.len());
+    for y in 0..self.dimension.1 {
+      for x in 0..self.dimension.0 {
+        display.push(self.display_settings.apply(self.pixel(x, y)));
+      }
+    }
+    display
+  }
+
+  /// Copy the resolved linear radiance into an image, flipped to run top to bottom.
+  pub fn to_image(&self) -> Image {
+    let mut pixels = Vec::with_capacity(self.pixels.len());
+    for y in (0..self.dimension.1).rev() {
+      for x in 0..self.dimension.0 {
+        pixels.push(self.pixel(x, y));
+      }
+    }
+    Image::new(self.dimension.0, self.dimension.1, pixels)
+  }
+
+  pub fn write_exr<P: AsRef<Path>>(&self, path: P) -> Result<(), ImageError> {
+    imageio::write_exr(path, &self.to_image())
+  }
+
+  pub fn write_pfm<P: AsRef<Path>>(&self, path: P) -> Result<(), ImageError> {
+    imageio::write_pfm(path, &self.to_image())
+  }
+
+  /// Save the film, picking the format from the file extension. PNG, PPM and TGA are written
+  /// through the display pipeline; EXR and PFM keep the linear radiance.
+  pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ImageError> {
+    self.save_impl(path.as_ref(), &[])
+  }
+
+  /// Same as [`Film::save`], additionally embedding the metadata as PNG text chunks.
+  pub fn save_with_metadata<P: AsRef<Path>>(
+    &self,
+    path: P,
+    metadata: &RenderMetadata,
+  ) -> Result<(), ImageError> {
+    self.save_impl(path.as_ref(), &metadata.text_entries())
+  }
+
+  fn save_impl(&self, path: &Path, text: &[(String, String)]) -> Result<(), ImageError> {
+    let (width, height) = self.dimension;
+    let display = || -> Vec<[u8; 4]> {
+      let image = self.to_image();
+      let pixels = image.pixels().iter();
+      pixels.map(|c| self.display_settings.apply(*c)).collect()
+    };
+    match imageio::extension(path).as_str() {
+      "png" => imageio::write_png(path, width, height, &display(), text),
+      "ppm" => imageio::write_ppm(path, width, height, &display()),
+      "tga" => imageio::write_tga(path, width, height, &display()),
+      "exr" => self.write_exr(path),
+      "pfm" => self.write_pfm(path),
+      extension => Err(ImageError::UnsupportedFormat(extension.to_string())),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::fs;
+
+  /// Path of a scratch file for a test, unique to the test process.
+  fn temp_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("flux-film-{}-{}", std::process::id(), name))
+  }
+
+  /// A 3x2 film holding a different color in every pixel, with the display copy of each pixel
+  /// in top to bottom order.
+  fn film() -> (Film, Vec<[u8; 4]>) {
+    let mut film = Film::new(3, 2);
+    let mut expected = vec![[0; 4]; 6];
+    for y in 0..2 {
+      for x in 0..3 {
+        let color = Color::new(0.1 * x as f32, 0.5 * y as f32, 0.05 + 0.2 * (x + y) as f32);
+        film.add_sample(x, y, color, 1.0);
+        expected[((1 - y) * 3 + x) as usize] = DisplaySettings::default().apply(color);
+      }
+    }
+    (film, expected)
   }
 
-  pub fn write_pixel(&mut self, x: u32, y: u32, color: [u8; 4]) {
-    self.data[(y * self.dimension.0 + x) as usize] = color;
+  #[test]
+  fn save_png_with_metadata() {
+    let (film, expected) = film();
+    let metadata = RenderMetadata {
+      samples_per_pixel: 16,
+      render_time: Duration::from_millis(1500),
+      settings: RenderSettings::default(),
+    };
+    let path = temp_path("metadata.png");
+    film.save_with_metadata(&path, &metadata).unwrap();
+    let decoder = png::Decoder::new(fs::File::open(&path).unwrap());
+    let mut reader = decoder.read_info().unwrap();
+    let mut data = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut data).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!((info.width, info.height), (3, 2));
+    assert_eq!(info.color_type, png::ColorType::Rgb);
+    for (rgb, expected) in data.chunks_exact(3).zip(&expected) {
+      assert_eq!(rgb, &expected[..3]);
+    }
+    let text = &reader.info().uncompressed_latin1_text;
+    let entry = |keyword: &str| {
+      let chunk = text.iter().find(|chunk| chunk.keyword == keyword);
+      chunk.map(|chunk| chunk.text.as_str())
+    };
+    assert_eq!(entry("Software"), Some("flux"));
+    assert_eq!(entry("SamplesPerPixel"), Some("16"));
+    assert_eq!(entry("RenderTime"), Some("1.500s"));
+    assert!(entry("RenderSettings").is_some());
+  }
+
+  #[test]
+  fn save_ppm_and_tga() {
+    let (film, expected) = film();
+    let ppm = temp_path("film.ppm");
+    let tga = temp_path("film.tga");
+    film.save(&ppm).unwrap();
+    film.save(&tga).unwrap();
+    let (ppm_bytes, tga_bytes) = (fs::read(&ppm).unwrap(), fs::read(&tga).unwrap());
+    fs::remove_file(&ppm).unwrap();
+    fs::remove_file(&tga).unwrap();
+
+    let header = b"P6\n3 2\n255\n";
+    assert_eq!(&ppm_bytes[..header.len()], header);
+    let rgb = expected.iter().flat_map(|p| [p[0], p[1], p[2]]);
+    assert!(ppm_bytes[header.len()..].iter().copied().eq(rgb));
+
+    assert_eq!(tga_bytes[2], 2);
+    assert_eq!(tga_bytes[12..18], [3, 0, 2, 0, 24, 0x20]);
+    let bgr = expected.iter().flat_map(|p| [p[2], p[1], p[0]]);
+    assert!(tga_bytes[18..].iter().copied().eq(bgr));
+  }
+
+  #[test]
+  fn save_rejects_unknown_formats() {
+    let (film, _) = film();
+    match film.save(temp_path("film.bmp")) {
+      Err(ImageError::UnsupportedFormat(extension)) => assert_eq!(extension, "bmp"),
+      _ => panic!("bmp was accepted"),
+    }
   }
 }
@@ -0,0 +1,106 @@
+use glam::Vec2;
+use std::f32::consts::PI;
+
+/// Pixel reconstruction filter. Every filter is separable and is evaluated at the offset
+/// between a sample and a pixel center, in pixels.
+#[derive(Clone, Copy, Debug)]
+pub enum Filter {
+  /// Equal weight for every sample in the box; a radius of 0.5 keeps samples in their own pixel.
+  Box {
+    radius: f32,
+  },
+  Triangle {
+    radius: f32,
+  },
+  /// Gaussian with standard deviation `sigma`, shifted down to reach zero at the radius.
+  Gaussian {
+    radius: f32,
+    sigma: f32,
+  },
+  /// Mitchell-Netravali cubic. `b = c = 1/3` is the commonly recommended choice.
+  Mitchell {
+    radius: f32,
+    b: f32,
+    c: f32,
+  },
+  /// Sinc windowed by a sinc stretched to the radius, which is also the number of lobes.
+  Lanczos {
+    radius: f32,
+  },
+}
+
+/// A box of radius 0.5, so every sample only counts towards the pixel it was taken in. Wider
+/// filters such as the Gaussian trade sharpness for less aliasing and have to be asked for.
+impl Default for Filter {
+  fn default() -> Self {
+    Filter::Box { radius: 0.5 }
+  }
+}
+
+fn gaussian(x: f32, sigma: f32) -> f32 {
+  (-x * x / (2.0 * sigma * sigma)).exp() / (2.0 * PI * sigma * sigma).sqrt()
+}
+
+fn mitchell(x: f32, b: f32, c: f32) -> f32 {
+  let x = x.abs();
+  if x <= 1.0 {
+    ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+      + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+      + (6.0 - 2.0 * b))
+      / 6.0
+  } else if x <= 2.0 {
+    ((-b - 6.0 * c) * x * x * x
+      + (6.0 * b + 30.0 * c) * x * x
+      + (-12.0 * b - 48.0 * c) * x
+      + (8.0 * b + 24.0 * c))
+      / 6.0
+  } else {
+    0.0
+  }
+}
+
+fn sinc(x: f32) -> f32 {
+  if x.abs() < 1e-5 {
+    1.0
+  } else {
+    (PI * x).sin() / (PI * x)
+  }
+}
+
+impl Filter {
+  pub fn radius(&self) -> f32 {
+    match *self {
+      Filter::Box { radius }
+      | Filter::Triangle { radius }
+      | Filter::Gaussian { radius, .. }
+      | Filter::Mitchell { radius, .. }
+      | Filter::Lanczos { radius } => radius,
+    }
+  }
+
+  fn evaluate_1d(&self, x: f32) -> f32 {
+    let radius = self.radius();
+    // the box is half open, so that a sample on the border of two pixels only counts in one
+    let inside = match self {
+      Filter::Box { .. } => (-radius..radius).contains(&x),
+      _ => x.abs() < radius,
+    };
+    if !inside {
+      return 0.0;
+    }
+    match *self {
+      Filter::Box { .. } => 1.0,
+      Filter::Triangle { .. } => radius - x.abs(),
+      Filter::Gaussian { sigma, .. } => (gaussian(x, sigma) - gaussian(radius, sigma)).max(0.0),
+      // the cubic is defined on [-2, 2]
+      Filter::Mitchell { b, c, .. } => mitchell(2.0 * x / radius, b, c),
+      Filter::Lanczos { .. } => sinc(x) * sinc(x / radius),
+    }
+  }
+
+  /// Weight of a sample at `offset` from the pixel center. May be negative for filters with
+  /// negative lobes.
+  pub fn evaluate(&self, offset: Vec2) -> f32 {
+    self.evaluate_1d(offset.x) * self.evaluate_1d(offset.y)
+  }
+}
@@ -1,9 +1,10 @@
 use glam::{Affine3A, Mat3A, Vec2, Vec3A};
 
-use super::shape::Shape;
+use super::{material::Material, shape::Shape, transform::transform_normal};
 
 pub(super) struct Hit<'a> {
   pub shape: Option<&'a Shape>,
+  pub material: Option<&'a Material>,
   pub p: Vec3A,
   pub ng: Vec3A,
   pub ns: Vec3A,
@@ -12,12 +13,15 @@ pub(super) struct Hit<'a> {
   pub dpdu: Vec3A,
   pub dpdv: Vec3A,
   pub front: bool,
+  /// Transform that placed the hit shape in the world at the time of the ray.
+  pub object_to_world: Affine3A,
 }
 
 impl<'a> Default for Hit<'a> {
   fn default() -> Self {
     Self {
       shape: None,
+      material: None,
       p: Vec3A::ZERO,
       ng: Vec3A::ZERO,
       ns: Vec3A::ZERO,
@@ -26,22 +30,118 @@ impl<'a> Default for Hit<'a> {
       dpdu: Vec3A::ZERO,
       dpdv: Vec3A::ZERO,
       front: false,
+      object_to_world: Affine3A::IDENTITY,
     }
   }
 }
 
+/// Smallest cosine between a perturbed shading normal and the geometric normal.
+const MIN_SHADING_COS: f32 = 0.01;
+
 impl<'a> Hit<'a> {
-  pub fn local_to_world(&self, v: Vec3A) -> Vec3A {
+  /// Carry a hit found in object space over into the world.
+  pub fn transform(&mut self, object_to_world: Affine3A, world_to_object: &Affine3A) {
+    self.p = object_to_world.transform_point3a(self.p);
+    self.ng = transform_normal(world_to_object, self.ng);
+    self.ns = transform_normal(world_to_object, self.ns);
+    self.dpdu = object_to_world.transform_vector3a(self.dpdu);
+    self.dpdv = object_to_world.transform_vector3a(self.dpdv);
+    self.object_to_world = object_to_world;
+  }
+
+  /// Orthonormal shading frame around `ns`, with its tangent following `dpdu` and its bitangent
+  /// on the side of `dpdv`. The frame is left-handed where the uv mapping is mirrored, so that
+  /// tangent space normal maps keep their orientation.
+  fn frame(&self) -> Mat3A {
     assert!(self.ns.is_normalized());
-    let tangent = self.dpdu.normalize();
+    let tangent = (self.dpdu - self.ns * self.ns.dot(self.dpdu))
+      .try_normalize()
+      .unwrap_or_else(|| self.ns.any_orthonormal_vector());
     let bitangent = self.ns.cross(tangent);
-    Mat3A::from_cols(tangent, bitangent, self.ns).mul_vec3a(v)
+    let bitangent = if bitangent.dot(self.dpdv) < 0.0 {
+      -bitangent
+    } else {
+      bitangent
+    };
+    Mat3A::from_cols(tangent, bitangent, self.ns)
+  }
+
+  pub fn local_to_world(&self, v: Vec3A) -> Vec3A {
+    self.frame().mul_vec3a(v)
   }
 
   pub fn world_to_local(&self, v: Vec3A) -> Vec3A {
-    assert!(self.ns.is_normalized());
-    let tangent = self.dpdu.normalize();
-    let bitangent = self.ns.cross(tangent);
-    Mat3A::from_cols(tangent, bitangent, self.ns).transpose().mul_vec3a(v)
+    self.frame().transpose().mul_vec3a(v)
+  }
+
+  /// Replace the shading normal with a perturbed one. A normal that falls below the geometric
+  /// horizon, on the side of the current shading normal, is lifted back above it.
+  pub fn set_shading_normal(&mut self, ns: Vec3A) {
+    let ng = if self.ng.dot(self.ns) < 0.0 {
+      -self.ng
+    } else {
+      self.ng
+    };
+    let cos = ns.dot(ng);
+    self.ns = if cos < MIN_SHADING_COS {
+      // keep the direction of the normal around `ng` and only raise its elevation
+      let tangent = (ns - cos * ng).try_normalize().unwrap_or(Vec3A::ZERO);
+      let sin = (1.0 - MIN_SHADING_COS * MIN_SHADING_COS).sqrt();
+      (MIN_SHADING_COS * ng + sin * tangent).normalize()
+    } else {
+      ns
+    };
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{math::uniform_sample_sphere, raytrace::rng::Pcg32};
+
+  #[test]
+  fn shading_normals_stay_above_the_horizon() {
+    let mut rng = Pcg32::default();
+    let ng = Vec3A::new(0.3, -0.4, 1.0).normalize();
+    for i in 0..1000 {
+      // flip the shading normal for half of the hits to cover both sides of the surface
+      let side = if i % 2 == 0 { 1.0 } else { -1.0 };
+      let mut hit = Hit {
+        ng,
+        ns: ng * side,
+        ..Default::default()
+      };
+      let u = Vec2::new(rng.uniform_f32(), rng.uniform_f32());
+      let ns = uniform_sample_sphere(&u);
+      hit.set_shading_normal(ns);
+      assert!(hit.ns.is_normalized());
+      assert!(
+        hit.ns.dot(ng) * side >= MIN_SHADING_COS - 1e-6,
+        "{ns} became {}",
+        hit.ns
+      );
+      if ns.dot(ng) * side >= MIN_SHADING_COS {
+        assert_eq!(hit.ns, ns);
+      }
+    }
+  }
+
+  #[test]
+  fn frame_follows_the_uv_orientation() {
+    for dpdv in [Vec3A::Y, -Vec3A::Y] {
+      let hit = Hit {
+        ng: Vec3A::Z,
+        ns: Vec3A::Z,
+        dpdu: Vec3A::new(2.0, 0.0, 0.1),
+        dpdv,
+        ..Default::default()
+      };
+      // a normal map tilting towards +v leans towards dpdv whichever way the uvs run
+      let n = hit.local_to_world(Vec3A::new(0.0, 0.5, 1.0).normalize());
+      assert!(n.dot(dpdv) > 0.0);
+      assert!(hit.local_to_world(Vec3A::X).dot(hit.dpdu) > 0.0);
+      let v = Vec3A::new(0.2, -0.6, 0.7);
+      assert!((hit.world_to_local(hit.local_to_world(v)) - v).length() < 1e-6);
+    }
   }
 }
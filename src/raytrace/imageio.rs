@@ -0,0 +1,651 @@
+use crate::math::Color;
+use std::{
+  fs,
+  io::{BufRead, BufReader, BufWriter, Read, Write},
+  path::Path,
+};
+
+#[derive(Debug)]
+pub enum ImageError {
+  Io(std::io::Error),
+  InvalidData(String),
+  UnsupportedFormat(String),
+  Encoding(String),
+}
+
+impl From<std::io::Error> for ImageError {
+  fn from(e: std::io::Error) -> Self {
+    ImageError::Io(e)
+  }
+}
+
+/// How the integer values of low dynamic range formats map to linear values. Color maps are
+/// usually sRGB encoded, while maps holding data such as roughness are linear.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorEncoding {
+  Srgb,
+  Linear,
+}
+
+impl ColorEncoding {
+  fn decode(&self, v: f32) -> f32 {
+    match self {
+      ColorEncoding::Linear => v,
+      ColorEncoding::Srgb if v <= 0.04045 => v / 12.92,
+      ColorEncoding::Srgb => ((v + 0.055) / 1.055).powf(2.4),
+    }
+  }
+}
+
+/// Linear RGB floating-point image stored top to bottom.
+#[derive(Clone)]
+pub struct Image {
+  width: u32,
+  height: u32,
+  pixels: Vec<Color>,
+}
+
+impl Image {
+  pub fn new(width: u32, height: u32, pixels: Vec<Color>) -> Self {
+    assert_eq!(pixels.len(), width as usize * height as usize);
+    Self {
+      width,
+      height,
+      pixels,
+    }
+  }
+
+  /// Load an image, picking the decoder from the file extension. Low dynamic range formats are
+  /// taken to be sRGB encoded.
+  pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ImageError> {
+    Self::load_with_encoding(path, ColorEncoding::Srgb)
+  }
+
+  /// Load an image, decoding low dynamic range formats with `encoding`. High dynamic range
+  /// formats are always linear.
+  pub fn load_with_encoding<P: AsRef<Path>>(
+    path: P,
+    encoding: ColorEncoding,
+  ) -> Result<Self, ImageError> {
+    let path = path.as_ref();
+    let extension = extension(path);
+    match extension.as_str() {
+      "hdr" | "pic" => read_hdr(path),
+      "pfm" => read_pfm(path),
+      "png" => read_png(path, encoding),
+      "jpg" | "jpeg" => read_jpeg(path, encoding),
+      _ => Err(ImageError::UnsupportedFormat(extension)),
+    }
+  }
+
+  pub fn width(&self) -> u32 {
+    self.width
+  }
+
+  pub fn height(&self) -> u32 {
+    self.height
+  }
+
+  pub fn pixels(&self) -> &[Color] {
+    &self.pixels
+  }
+
+  pub fn pixel(&self, x: u32, y: u32) -> Color {
+    self.pixels[y as usize * self.width as usize + x as usize]
+  }
+}
+
+/// Lowercase file extension used to pick an image format.
+pub(super) fn extension(path: &Path) -> String {
+  path
+    .extension()
+    .and_then(|e| e.to_str())
+    .map(|e| e.to_ascii_lowercase())
+    .unwrap_or_default()
+}
+
+/// Number of pixels of an image read from a header, rejecting empty images and ones too large to
+/// address.
+fn pixel_count(width: u32, height: u32) -> Result<usize, ImageError> {
+  if width == 0 || height == 0 {
+    return Err(ImageError::InvalidData("empty image".into()));
+  }
+  (width as usize)
+    .checked_mul(height as usize)
+    .ok_or_else(|| ImageError::InvalidData("image too large".into()))
+}
+
+fn read_header_line<R: BufRead>(reader: &mut R) -> Result<String, ImageError> {
+  let mut line = String::new();
+  if reader.read_line(&mut line)? == 0 {
+    return Err(ImageError::InvalidData("unexpected end of header".into()));
+  }
+  Ok(line.trim_end().to_string())
+}
+
+/// Read a Radiance RGBE image, either flat or new-style run-length encoded.
+pub fn read_hdr<P: AsRef<Path>>(path: P) -> Result<Image, ImageError> {
+  let mut reader = BufReader::new(fs::File::open(path)?);
+  let magic = read_header_line(&mut reader)?;
+  if !magic.starts_with("#?") {
+    return Err(ImageError::InvalidData("missing Radiance signature".into()));
+  }
+  loop {
+    let line = read_header_line(&mut reader)?;
+    if line.is_empty() {
+      break;
+    }
+    if let Some(format) = line.strip_prefix("FORMAT=") {
+      if format != "32-bit_rle_rgbe" {
+        return Err(ImageError::UnsupportedFormat(format.to_string()));
+      }
+    }
+  }
+  let resolution = read_header_line(&mut reader)?;
+  let tokens = resolution.split_whitespace().collect::<Vec<_>>();
+  let (width, height) = match tokens.as_slice() {
+    ["-Y", h, "+X", w] => (w.parse::<u32>(), h.parse::<u32>()),
+    _ => {
+      return Err(ImageError::UnsupportedFormat(format!(
+        "image orientation {}",
+        resolution
+      )))
+    }
+  };
+  let (width, height) = match (width, height) {
+    (Ok(w), Ok(h)) => (w, h),
+    _ => return Err(ImageError::InvalidData("invalid resolution".into())),
+  };
+  let count = pixel_count(width, height)?;
+
+  let mut data = Vec::new();
+  reader.read_to_end(&mut data)?;
+  // the shortest scanline is a header and a two byte run per 127 pixels of every channel, or
+  // four bytes per pixel without run-length encoding
+  let rle_width = (8..0x8000).contains(&width);
+  let min_scanline = if rle_width {
+    4 + 8 * (width as usize).div_ceil(127)
+  } else {
+    4 * width as usize
+  };
+  if min_scanline
+    .checked_mul(height as usize)
+    .is_none_or(|size| size > data.len())
+  {
+    return Err(ImageError::InvalidData("truncated pixel data".into()));
+  }
+  let mut cursor = 0;
+  let mut next = || -> Result<u8, ImageError> {
+    let byte = data
+      .get(cursor)
+      .copied()
+      .ok_or_else(|| ImageError::InvalidData("truncated pixel data".into()))?;
+    cursor += 1;
+    Ok(byte)
+  };
+
+  let mut pixels = Vec::with_capacity(count);
+  let mut scanline = vec![[0u8; 4]; width as usize];
+  for _ in 0..height {
+    let header = [next()?, next()?, next()?, next()?];
+    let rle = rle_width && header[0] == 2 && header[1] == 2 && header[2] < 128;
+    if rle {
+      if ((header[2] as u32) << 8 | header[3] as u32) != width {
+        return Err(ImageError::InvalidData("scanline width mismatch".into()));
+      }
+      // each channel is run-length encoded separately
+      for channel in 0..4 {
+        let mut x = 0;
+        while x < width as usize {
+          let count = next()? as usize;
+          if count > 128 {
+            let count = count - 128;
+            let value = next()?;
+            for texel in scanline.iter_mut().skip(x).take(count) {
+              texel[channel] = value;
+            }
+            x += count;
+          } else {
+            for texel in scanline.iter_mut().skip(x).take(count) {
+              texel[channel] = next()?;
+            }
+            x += count;
+          }
+        }
+      }
+    } else {
+      scanline[0] = header;
+      for texel in scanline.iter_mut().skip(1) {
+        *texel = [next()?, next()?, next()?, next()?];
+      }
+    }
+    pixels.extend(scanline.iter().map(|rgbe| {
+      if rgbe[3] == 0 {
+        Color::BLACK
+      } else {
+        let f = 2f32.powi(rgbe[3] as i32 - 136);
+        Color::new(rgbe[0] as f32 * f, rgbe[1] as f32 * f, rgbe[2] as f32 * f)
+      }
+    }));
+  }
+  Ok(Image::new(width, height, pixels))
+}
+
+/// Read a portable float map. Both the RGB (`PF`) and greyscale (`Pf`) variants are supported.
+pub fn read_pfm<P: AsRef<Path>>(path: P) -> Result<Image, ImageError> {
+  let file = fs::File::open(path)?;
+  let file_size = file.metadata()?.len();
+  let mut reader = BufReader::new(file);
+  let mut tokens = Vec::new();
+  while tokens.len() < 4 {
+    let line = read_header_line(&mut reader)?;
+    tokens.extend(line.split_whitespace().map(str::to_string));
+  }
+  let channels = match tokens[0].as_str() {
+    "PF" => 3,
+    "Pf" => 1,
+    _ => return Err(ImageError::InvalidData("missing PFM signature".into())),
+  };
+  let (width, height, scale) = match (
+    tokens[1].parse::<u32>(),
+    tokens[2].parse::<u32>(),
+    tokens[3].parse::<f32>(),
+  ) {
+    (Ok(w), Ok(h), Ok(s)) => (w, h, s),
+    _ => return Err(ImageError::InvalidData("invalid PFM header".into())),
+  };
+
+  // check the size against the file before allocating for it
+  let count = pixel_count(width, height)?;
+  let size = count
+    .checked_mul(channels * 4)
+    .filter(|&size| size as u64 <= file_size)
+    .ok_or_else(|| ImageError::InvalidData("image larger than the file".into()))?;
+  let mut data = vec![0u8; size];
+  reader.read_exact(&mut data)?;
+  let values = data
+    .chunks_exact(4)
+    .map(|b| {
+      let bytes = [b[0], b[1], b[2], b[3]];
+      // a negative scale marks little-endian data
+      if scale < 0.0 {
+        f32::from_le_bytes(bytes)
+      } else {
+        f32::from_be_bytes(bytes)
+      }
+    })
+    .collect::<Vec<_>>();
+
+  // scanlines are stored bottom to top
+  let row_len = width as usize * channels;
+  let mut pixels = Vec::with_capacity(count);
+  for row in values.chunks_exact(row_len).rev() {
+    if channels == 3 {
+      pixels.extend(row.chunks_exact(3).map(|c| Color::new(c[0], c[1], c[2])));
+    } else {
+      pixels.extend(row.iter().map(|v| Color::splat(*v)));
+    }
+  }
+  Ok(Image::new(width, height, pixels))
+}
+
+/// Write a little-endian RGB portable float map.
+pub fn write_pfm<P: AsRef<Path>>(path: P, image: &Image) -> Result<(), ImageError> {
+  let mut out = BufWriter::new(fs::File::create(path)?);
+  write!(out, "PF\n{} {}\n-1.0\n", image.width(), image.height())?;
+  // scanlines are stored bottom to top
+  for row in image.pixels().chunks_exact(image.width() as usize).rev() {
+    for c in row {
+      out.write_all(&c.r.to_le_bytes())?;
+      out.write_all(&c.g.to_le_bytes())?;
+      out.write_all(&c.b.to_le_bytes())?;
+    }
+  }
+  out.flush()?;
+  Ok(())
+}
+
+fn write_exr_attribute<W: Write>(
+  out: &mut W,
+  name: &str,
+  kind: &str,
+  value: &[u8],
+) -> std::io::Result<()> {
+  out.write_all(name.as_bytes())?;
+  out.write_all(&[0])?;
+  out.write_all(kind.as_bytes())?;
+  out.write_all(&[0])?;
+  out.write_all(&(value.len() as i32).to_le_bytes())?;
+  out.write_all(value)
+}
+
+/// Write an uncompressed scanline OpenEXR image with 32-bit float R, G and B channels.
+pub fn write_exr<P: AsRef<Path>>(path: P, image: &Image) -> Result<(), ImageError> {
+  let (width, height) = (image.width() as usize, image.height() as usize);
+
+  // magic number and version 2, single-part scanline
+  let mut header = Vec::new();
+  header.extend_from_slice(&20000630i32.to_le_bytes());
+  header.extend_from_slice(&2i32.to_le_bytes());
+
+  // channels must be listed in alphabetical order
+  let channels = ["B", "G", "R"];
+  let mut chlist = Vec::new();
+  for name in channels {
+    chlist.extend_from_slice(name.as_bytes());
+    chlist.push(0);
+    chlist.extend_from_slice(&2i32.to_le_bytes()); // FLOAT
+    chlist.extend_from_slice(&[0, 0, 0, 0]); // pLinear and reserved
+    chlist.extend_from_slice(&1i32.to_le_bytes()); // xSampling
+    chlist.extend_from_slice(&1i32.to_le_bytes()); // ySampling
+  }
+  chlist.push(0);
+  let mut window = Vec::new();
+  for v in [0, 0, width as i32 - 1, height as i32 - 1] {
+    window.extend_from_slice(&v.to_le_bytes());
+  }
+  write_exr_attribute(&mut header, "channels", "chlist", &chlist)?;
+  write_exr_attribute(&mut header, "compression", "compression", &[0])?;
+  write_exr_attribute(&mut header, "dataWindow", "box2i", &window)?;
+  write_exr_attribute(&mut header, "displayWindow", "box2i", &window)?;
+  write_exr_attribute(&mut header, "lineOrder", "lineOrder", &[0])?;
+  write_exr_attribute(
+    &mut header,
+    "pixelAspectRatio",
+    "float",
+    &1f32.to_le_bytes(),
+  )?;
+  write_exr_attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8])?;
+  write_exr_attribute(
+    &mut header,
+    "screenWindowWidth",
+    "float",
+    &1f32.to_le_bytes(),
+  )?;
+  header.push(0);
+
+  let mut out = BufWriter::new(fs::File::create(path)?);
+  out.write_all(&header)?;
+
+  // offset table, one uncompressed scanline per chunk
+  let chunk_size = 8 + width * channels.len() * 4;
+  let table_end = header.len() + height * 8;
+  for y in 0..height {
+    out.write_all(&((table_end + y * chunk_size) as u64).to_le_bytes())?;
+  }
+
+  for (y, row) in image.pixels().chunks_exact(width).enumerate() {
+    out.write_all(&(y as i32).to_le_bytes())?;
+    out.write_all(&((width * channels.len() * 4) as i32).to_le_bytes())?;
+    for channel in channels {
+      for c in row {
+        let v = match channel {
+          "R" => c.r,
+          "G" => c.g,
+          _ => c.b,
+        };
+        out.write_all(&v.to_le_bytes())?;
+      }
+    }
+  }
+  out.flush()?;
+  Ok(())
+}
+
+/// Read an 8 or 16-bit PNG of any color type. Alpha is dropped.
+pub fn read_png<P: AsRef<Path>>(path: P, encoding: ColorEncoding) -> Result<Image, ImageError> {
+  let mut decoder = png::Decoder::new(BufReader::new(fs::File::open(path)?));
+  // expand palettes and low bit depths to 8-bit samples
+  decoder.set_transformations(png::Transformations::EXPAND);
+  let mut reader = decoder
+    .read_info()
+    .map_err(|e| ImageError::InvalidData(e.to_string()))?;
+  let mut data = vec![0; reader.output_buffer_size()];
+  let info = reader
+    .next_frame(&mut data)
+    .map_err(|e| ImageError::InvalidData(e.to_string()))?;
+  let channels = match info.color_type {
+    png::ColorType::Grayscale => 1,
+    png::ColorType::GrayscaleAlpha => 2,
+    png::ColorType::Rgb => 3,
+    png::ColorType::Rgba => 4,
+    png::ColorType::Indexed => {
+      return Err(ImageError::InvalidData("palette was not expanded".into()))
+    }
+  };
+  let sample = |i: usize| match info.bit_depth {
+    png::BitDepth::Sixteen => u16::from_be_bytes([data[2 * i], data[2 * i + 1]]) as f32 / 65535.0,
+    _ => data[i] as f32 / 255.0,
+  };
+  let pixels = (0..(info.width * info.height) as usize)
+    .map(|p| {
+      let base = p * channels;
+      let (r, g, b) = if channels < 3 {
+        (sample(base), sample(base), sample(base))
+      } else {
+        (sample(base), sample(base + 1), sample(base + 2))
+      };
+      Color::new(encoding.decode(r), encoding.decode(g), encoding.decode(b))
+    })
+    .collect();
+  Ok(Image::new(info.width, info.height, pixels))
+}
+
+/// Read a baseline or progressive JPEG with 8-bit samples.
+pub fn read_jpeg<P: AsRef<Path>>(path: P, encoding: ColorEncoding) -> Result<Image, ImageError> {
+  let mut decoder = jpeg_decoder::Decoder::new(BufReader::new(fs::File::open(path)?));
+  let data = decoder
+    .decode()
+    .map_err(|e| ImageError::InvalidData(e.to_string()))?;
+  let info = decoder
+    .info()
+    .ok_or_else(|| ImageError::InvalidData("missing JPEG frame".into()))?;
+  if info.pixel_format == jpeg_decoder::PixelFormat::L16 {
+    return Err(ImageError::UnsupportedFormat("16-bit JPEG".into()));
+  }
+  let channels = info.pixel_format.pixel_bytes();
+  let sample = |i: usize| data[i] as f32 / 255.0;
+  let pixels = (0..info.width as usize * info.height as usize)
+    .map(|p| {
+      let base = p * channels;
+      let (r, g, b) = match info.pixel_format {
+        jpeg_decoder::PixelFormat::RGB24 => (sample(base), sample(base + 1), sample(base + 2)),
+        // the decoder hands out inverted inks, so the key scales what the other inks let through
+        jpeg_decoder::PixelFormat::CMYK32 => {
+          let k = sample(base + 3);
+          (sample(base) * k, sample(base + 1) * k, sample(base + 2) * k)
+        }
+        _ => (sample(base), sample(base), sample(base)),
+      };
+      Color::new(encoding.decode(r), encoding.decode(g), encoding.decode(b))
+    })
+    .collect();
+  Ok(Image::new(info.width as u32, info.height as u32, pixels))
+}
+
+/// Write 8-bit RGB data as a PNG, storing each `(keyword, text)` pair in a tEXt chunk.
+pub fn write_png<P: AsRef<Path>>(
+  path: P,
+  width: u32,
+  height: u32,
+  data: &[[u8; 4]],
+  text: &[(String, String)],
+) -> Result<(), ImageError> {
+  let out = BufWriter::new(fs::File::create(path)?);
+  let mut encoder = png::Encoder::new(out, width, height);
+  encoder.set_color(png::ColorType::Rgb);
+  encoder.set_depth(png::BitDepth::Eight);
+  encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+  for (keyword, value) in text {
+    encoder
+      .add_text_chunk(keyword.clone(), value.clone())
+      .map_err(|e| ImageError::Encoding(e.to_string()))?;
+  }
+  let rgb = data
+    .iter()
+    .flat_map(|p| [p[0], p[1], p[2]])
+    .collect::<Vec<_>>();
+  encoder
+    .write_header()
+    .and_then(|mut writer| writer.write_image_data(&rgb))
+    .map_err(|e| ImageError::Encoding(e.to_string()))
+}
+
+/// Write 8-bit RGB data as a binary (P6) portable pixmap.
+pub fn write_ppm<P: AsRef<Path>>(
+  path: P,
+  width: u32,
+  height: u32,
+  data: &[[u8; 4]],
+) -> Result<(), ImageError> {
+  let mut out = BufWriter::new(fs::File::create(path)?);
+  write!(out, "P6\n{} {}\n255\n", width, height)?;
+  for p in data {
+    out.write_all(&p[..3])?;
+  }
+  out.flush()?;
+  Ok(())
+}
+
+/// Write 8-bit RGB data as an uncompressed true-color Targa image.
+pub fn write_tga<P: AsRef<Path>>(
+  path: P,
+  width: u32,
+  height: u32,
+  data: &[[u8; 4]],
+) -> Result<(), ImageError> {
+  if width > u16::MAX as u32 || height > u16::MAX as u32 {
+    return Err(ImageError::Encoding(
+      "TGA dimensions are limited to 65535".into(),
+    ));
+  }
+  let mut out = BufWriter::new(fs::File::create(path)?);
+  let mut header = [0u8; 18];
+  header[2] = 2; // uncompressed true-color
+  header[12..14].copy_from_slice(&(width as u16).to_le_bytes());
+  header[14..16].copy_from_slice(&(height as u16).to_le_bytes());
+  header[16] = 24; // bits per pixel
+  header[17] = 0x20; // top-left origin
+  out.write_all(&header)?;
+  for p in data {
+    out.write_all(&[p[2], p[1], p[0]])?;
+  }
+  out.flush()?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  type Reader = fn(&Path) -> Result<Image, ImageError>;
+
+  /// Path of a scratch file for a test, unique to the test process.
+  fn temp_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("flux-{}-{}", std::process::id(), name))
+  }
+
+  fn read_bytes(name: &str, bytes: &[u8], read: Reader) -> Result<Image, ImageError> {
+    let path = temp_path(name);
+    fs::write(&path, bytes).unwrap();
+    let image = read(&path);
+    fs::remove_file(&path).unwrap();
+    image
+  }
+
+  #[test]
+  fn corrupt_headers_are_rejected() {
+    let hdr = |resolution: &str| {
+      let mut bytes = format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n{resolution}\n").into_bytes();
+      bytes.extend([128, 128, 128, 129]);
+      bytes
+    };
+    let pfm = |header: &str| {
+      let mut bytes = format!("PF\n{header}\n-1.0\n").into_bytes();
+      bytes.extend([0; 12]);
+      bytes
+    };
+    let cases: [(&str, Vec<u8>, Reader, bool); 6] = [
+      ("ok.hdr", hdr("-Y 1 +X 1"), |p| read_hdr(p), true),
+      ("empty.hdr", hdr("-Y 1 +X 0"), |p| read_hdr(p), false),
+      (
+        "huge.hdr",
+        hdr("-Y 4294967295 +X 4294967295"),
+        |p| read_hdr(p),
+        false,
+      ),
+      ("ok.pfm", pfm("1 1"), |p| read_pfm(p), true),
+      ("empty.pfm", pfm("0 1"), |p| read_pfm(p), false),
+      (
+        "huge.pfm",
+        pfm("4294967295 4294967295"),
+        |p| read_pfm(p),
+        false,
+      ),
+    ];
+    for (name, bytes, read, valid) in cases {
+      match read_bytes(name, &bytes, read) {
+        Ok(_) => assert!(valid, "{name} was accepted"),
+        Err(ImageError::InvalidData(_)) => assert!(!valid, "{name} was rejected"),
+        Err(e) => panic!("{name}: {e:?}"),
+      }
+    }
+  }
+
+  fn gradient(width: u32, height: u32) -> Image {
+    let pixels = (0..width * height)
+      .map(|i| Color::new(i as f32, 0.5 / (i + 1) as f32, -1e-3 * i as f32))
+      .collect();
+    Image::new(width, height, pixels)
+  }
+
+  #[test]
+  fn pfm_round_trip() {
+    let image = gradient(3, 2);
+    let path = temp_path("round-trip.pfm");
+    write_pfm(&path, &image).unwrap();
+    let read = read_pfm(&path);
+    fs::remove_file(&path).unwrap();
+    let read = read.unwrap();
+    assert_eq!((read.width(), read.height()), (3, 2));
+    for (a, b) in image.pixels().iter().zip(read.pixels()) {
+      assert_eq!((a.r, a.g, a.b), (b.r, b.g, b.b));
+    }
+  }
+
+  #[test]
+  fn exr_header() {
+    let path = temp_path("header.exr");
+    write_exr(&path, &gradient(3, 2)).unwrap();
+    let bytes = fs::read(&path);
+    fs::remove_file(&path).unwrap();
+    let bytes = bytes.unwrap();
+
+    // magic number, then version 2 with no flags set
+    assert_eq!(bytes[..8], [0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0]);
+    let mut chlist = b"channels\0chlist\0".to_vec();
+    chlist.extend(55i32.to_le_bytes());
+    for name in [b'B', b'G', b'R'] {
+      chlist.extend([name, 0, 2, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0]);
+    }
+    assert_eq!(bytes[8..8 + chlist.len()], chlist);
+    let mut window = b"dataWindow\0box2i\0".to_vec();
+    window.extend([16, 0, 0, 0]);
+    for v in [0i32, 0, 2, 1] {
+      window.extend(v.to_le_bytes());
+    }
+    assert!(bytes.windows(window.len()).any(|w| w == window));
+
+    // the null-terminated header is followed by an offset table pointing at two scanlines of
+    // three float channels
+    let scanline = 8 + 3 * 3 * 4;
+    let table = bytes.len() - 2 * 8 - 2 * scanline;
+    assert_eq!(bytes[table - 1], 0);
+    for y in 0..2 {
+      let offset = u64::from_le_bytes(bytes[table + 8 * y..table + 8 * y + 8].try_into().unwrap());
+      let start = table + 2 * 8 + y * scanline;
+      assert_eq!(offset as usize, start);
+      assert_eq!(bytes[start..start + 8], [y as u8, 0, 0, 0, 36, 0, 0, 0]);
+    }
+  }
+}
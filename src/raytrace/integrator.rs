@@ -1,10 +1,8 @@
-use glam::{Vec3, Vec3A};
+use glam::Vec3A;
 
 use super::{
-  accelerator::Accelerator,
-  bsdf::{Lambertian, BSDF},
-  hit::Hit,
-  sampler::Sampler,
+  accelerator::Accelerator, environment::Sky, hit::Hit, light::area_to_solid_angle_pdf,
+  medium::Medium, rng::Pcg32, sampler::Sampler,
 };
 use crate::math::{Color, Ray};
 
@@ -12,6 +10,23 @@ pub trait Integrator {
   fn li(&self, accel: &Accelerator, sampler: &mut dyn Sampler, ray: Ray, bounce: u32) -> Color;
 }
 
+/// Which [`Integrator`] the render engine creates for its workers.
+#[derive(Clone, Copy, Debug)]
+pub enum IntegratorKind {
+  Path,
+  /// Path tracing through participating media, which `Path` ignores.
+  VolumePath,
+}
+
+impl IntegratorKind {
+  pub(super) fn create(&self, max_bounce: u32) -> Box<dyn Integrator> {
+    match self {
+      IntegratorKind::Path => Box::new(PathIntegrator::new(max_bounce)),
+      IntegratorKind::VolumePath => Box::new(VolumePathIntegrator::new(max_bounce)),
+    }
+  }
+}
+
 pub struct PathIntegrator {
   max_bounce: u32,
   rr_threshold: f32,
@@ -26,42 +41,417 @@ impl PathIntegrator {
   }
 }
 
-fn sky_color(ray: &Ray) -> Color {
-  let t = 0.5 * (ray.direction.y + 1.0);
-  Color::new(1.0, 1.0, 1.0) * (1.0 - t) + Color::new(0.5, 0.7, 1.0) * t
+/// Russian roulette once the path has had a chance to pick up indirect light. Returns the
+/// compensated throughput, or `None` when the path ends.
+fn russian_roulette(throughput: Color, depth: u32, threshold: f32, u: f32) -> Option<Color> {
+  if depth > 3 && throughput.max_component() < threshold {
+    let q = (1.0 - throughput.max_component()).max(0.05);
+    if u < q {
+      return None;
+    }
+    return Some(throughput / (1.0 - q));
+  }
+  Some(throughput)
+}
+
+/// Veach's power heuristic with an exponent of two.
+fn power_heuristic(f_pdf: f32, g_pdf: f32) -> f32 {
+  let f = f_pdf * f_pdf;
+  let g = g_pdf * g_pdf;
+  if f + g == 0.0 {
+    0.0
+  } else {
+    f / (f + g)
+  }
+}
+
+/// State of a path under construction, with the steps that path tracers share.
+struct PathState {
+  radiance: Color,
+  throughput: Color,
+  ray: Ray,
+  /// Pdf of the direction the path took at its last vertex.
+  bsdf_pdf: f32,
+  /// The last bounce was a specular one, which light sampling cannot account for.
+  specular_bounce: bool,
+  bounce: u32,
+  first_bounce: u32,
+  /// Where the path last scattered, as emitters found by the BSDF sample are seen from there.
+  vertex: Vec3A,
+}
+
+impl PathState {
+  fn new(ray: Ray, bounce: u32) -> Self {
+    Self {
+      radiance: Color::BLACK,
+      throughput: Color::WHITE,
+      ray,
+      bsdf_pdf: 0.0,
+      specular_bounce: false,
+      bounce,
+      first_bounce: bounce,
+      vertex: ray.origin,
+    }
+  }
+
+  /// Add the light of the sky the path leaves the scene towards.
+  fn escape(&mut self, accel: &Accelerator) {
+    let lights = accel.lights();
+    let sky = accel.sky();
+    let weight = match sky {
+      // Environment maps are also reached through light sampling
+      Sky::Environment(map) if self.bounce != self.first_bounce && !self.specular_bounce => {
+        power_heuristic(
+          self.bsdf_pdf,
+          map.pdf(self.ray.direction) / lights.len() as f32,
+        )
+      }
+      _ => 1.0,
+    };
+    self.radiance = self.radiance + self.throughput * sky.radiance(self.ray.direction) * weight;
+  }
+
+  /// Continue through a surface that does not scatter, without counting a bounce.
+  fn pass_through(&mut self, hit: &Hit) {
+    self.ray = Ray {
+      origin: hit.p,
+      t_min: 0.001,
+      t_max: f32::INFINITY,
+      ..self.ray
+    };
+  }
+
+  /// Shade the surface at `hit` and continue the path from it. Emission found by the previous
+  /// BSDF sample and light sampled from a random emitter are added, weighted against each other,
+  /// then the BSDF picks the next direction. `visibility` gives the fraction of light that
+  /// arrives along a shadow ray leaving the surface. Returns `false` when the path ends.
+  fn scatter_surface(
+    &mut self,
+    accel: &Accelerator,
+    sampler: &mut dyn Sampler,
+    hit: &mut Hit,
+    max_bounce: u32,
+    rr_threshold: f32,
+    mut visibility: impl FnMut(&Hit, &Ray) -> Color,
+  ) -> bool {
+    let lights = accel.lights();
+    let material = hit.material.expect("Hit should carry a material");
+    let wo = -self.ray.direction;
+
+    if material.is_emissive() && hit.front {
+      if self.bounce == self.first_bounce || self.specular_bounce {
+        self.radiance = self.radiance + self.throughput * material.emission(hit);
+      } else {
+        let shape = hit.shape.expect("Hit should carry a shape");
+        let light_pdf = area_to_solid_angle_pdf(
+          1.0 / shape.area(&hit.object_to_world),
+          hit.p.distance(self.vertex),
+          hit.ng.dot(wo),
+        ) / lights.len() as f32;
+        let weight = power_heuristic(self.bsdf_pdf, light_pdf);
+        self.radiance = self.radiance + self.throughput * material.emission(hit) * weight;
+      }
+    }
+    if self.bounce >= max_bounce {
+      return false;
+    }
+
+    // Every bounce consumes the same sample dimensions, used or not, so that they line up
+    // across the samples of a pixel
+    let light_select = sampler.get_1d();
+    let light_sample = sampler.get_2d();
+    let bsdf_lobe = sampler.get_1d();
+    let bsdf_sample = sampler.get_2d();
+    let rr_sample = sampler.get_1d();
+    material.perturb_normal(hit);
+    let bsdf = material.bsdf(hit);
+    let specular = bsdf.is_specular();
+
+    // Next-event estimation: pick an emitter uniformly and trace a shadow ray to it
+    if !lights.is_empty() && !specular {
+      let index = ((light_select * lights.len() as f32) as usize).min(lights.len() - 1);
+      let light = &lights[index];
+      if let Some(ls) = light.sample_li(hit.p, self.ray.time, &light_sample) {
+        let light_pdf = ls.pdf / lights.len() as f32;
+        let mut pdf = 0.0;
+        let f = bsdf.eval(hit, &wo, &ls.wi, &mut pdf);
+        let cosine = ls.wi.dot(hit.ns).abs();
+        if !f.is_black() && light_pdf > 0.0 {
+          let shadow_ray = Ray {
+            origin: hit.p,
+            direction: ls.wi,
+            t_min: 0.001,
+            t_max: ls.distance - 0.001,
+            time: self.ray.time,
+          };
+          let tr = visibility(hit, &shadow_ray);
+          if !tr.is_black() {
+            let weight = if light.is_delta() {
+              1.0
+            } else {
+              power_heuristic(light_pdf, pdf)
+            };
+            self.radiance =
+              self.radiance + self.throughput * f * tr * ls.li * (cosine * weight / light_pdf);
+          }
+        }
+      }
+    }
+
+    // Continue the path by sampling the BSDF
+    let mut wi = Vec3A::default();
+    let f = bsdf.sample(
+      hit,
+      &wo,
+      &mut wi,
+      &mut self.bsdf_pdf,
+      bsdf_lobe,
+      &bsdf_sample,
+    );
+    if f.is_black() || self.bsdf_pdf == 0.0 {
+      return false;
+    }
+    self.specular_bounce = specular;
+    let cosine = wi.dot(hit.ns).abs();
+    self.throughput = self.throughput * f * (cosine / self.bsdf_pdf);
+    if !self.survive(rr_threshold, rr_sample) {
+      return false;
+    }
+    self.spawn(hit.p, wi, 0.001);
+    true
+  }
+
+  /// Play Russian roulette with the throughput, returning `false` when the path ends.
+  fn survive(&mut self, threshold: f32, u: f32) -> bool {
+    let depth = self.bounce - self.first_bounce;
+    match russian_roulette(self.throughput, depth, threshold, u) {
+      Some(throughput) => {
+        self.throughput = throughput;
+        true
+      }
+      None => false,
+    }
+  }
+
+  /// Start the next bounce of the path at `origin`, heading towards `direction`.
+  fn spawn(&mut self, origin: Vec3A, direction: Vec3A, t_min: f32) {
+    self.ray = Ray {
+      origin,
+      direction,
+      t_min,
+      t_max: f32::INFINITY,
+      time: self.ray.time,
+    };
+    self.vertex = origin;
+    self.bounce += 1;
+  }
 }
 
 impl Integrator for PathIntegrator {
   fn li(&self, accel: &Accelerator, sampler: &mut dyn Sampler, ray: Ray, bounce: u32) -> Color {
-    let mut hit = Hit::default();
-    let found_intersection = accel.intersect(&ray, &mut hit);
-    if !found_intersection {
-      return sky_color(&ray);
-    } else if bounce >= self.max_bounce {
-      return Color::BLACK;
+    let mut path = PathState::new(ray, bounce);
+    loop {
+      let mut hit = Hit::default();
+      if !accel.intersect(&path.ray, &mut hit) {
+        path.escape(accel);
+        break;
+      }
+      // Medium boundaries without a surface are invisible without media, and light sampling
+      // already sees through them
+      if hit.material.is_some_and(|material| material.is_interface()) {
+        path.pass_through(&hit);
+        continue;
+      }
+      let visibility = |_: &Hit, shadow_ray: &Ray| {
+        if accel.occluded(shadow_ray) {
+          Color::BLACK
+        } else {
+          Color::WHITE
+        }
+      };
+      if !path.scatter_surface(
+        accel,
+        sampler,
+        &mut hit,
+        self.max_bounce,
+        self.rr_threshold,
+        visibility,
+      ) {
+        break;
+      }
     }
+    path.radiance
+  }
+}
 
-    let wo = -ray.direction;
-    let mut wi = Vec3A::default();
-    let mut pdf = 0.0;
-    let bsdf = Lambertian::default();
-    let f = bsdf.sample(&hit, &wo, &mut wi, &mut pdf, &sampler.get_2d());
-    if f == Color::BLACK || pdf == 0.0 {
-      return Color::BLACK;
+/// Path tracer for scenes with participating media. Distances to scattering events are sampled
+/// in the medium around the path, and shadow rays carry the transmittance of the media they
+/// cross. Paths start in the medium of the scene, so the camera must not be inside a closed
+/// mesh with a medium of its own.
+pub struct VolumePathIntegrator {
+  max_bounce: u32,
+  rr_threshold: f32,
+}
+
+impl VolumePathIntegrator {
+  pub fn new(max_bounce: u32) -> Self {
+    Self {
+      max_bounce,
+      rr_threshold: 1.0,
     }
+  }
+}
 
-    let le = Color::BLACK;
-    let cosine = wi.dot(hit.ns).max(0.0);
-    let new_ray = Ray {
+/// Medium around a path leaving the surface of `hit` along `direction`. Only surfaces with a
+/// medium inside change it; the outside of every such surface is the medium of the scene.
+fn next_medium<'a>(
+  accel: &'a Accelerator,
+  hit: &Hit<'a>,
+  direction: Vec3A,
+  current: Option<&'a dyn Medium>,
+) -> Option<&'a dyn Medium> {
+  let material = hit.material.expect("Hit should carry a material");
+  match &material.interior {
+    Some(interior) if direction.dot(hit.ng) < 0.0 => Some(interior.as_ref()),
+    Some(_) => accel.medium(),
+    None => current,
+  }
+}
+
+/// Transmittance along a shadow ray through the media it crosses. Surfaces other than medium
+/// boundaries block it.
+fn transmittance<'a>(
+  accel: &'a Accelerator,
+  ray: &Ray,
+  medium: Option<&'a dyn Medium>,
+  rng: &mut Pcg32,
+) -> Color {
+  let mut ray = *ray;
+  let mut medium = medium;
+  let mut tr = Color::WHITE;
+  loop {
+    let mut hit = Hit::default();
+    let found = accel.intersect_shadow(&ray, &mut hit);
+    if found && !hit.material.is_some_and(|material| material.is_interface()) {
+      return Color::BLACK;
+    }
+    if let Some(medium) = medium {
+      let t_max = if found { hit.t } else { ray.t_max };
+      tr = tr * medium.tr(&ray, t_max, rng);
+    }
+    if !found || tr.is_black() {
+      return tr;
+    }
+    medium = next_medium(accel, &hit, ray.direction, medium);
+    ray = Ray {
       origin: hit.p,
-      direction: wi,
       t_min: 0.001,
-      t_max: f32::INFINITY,
+      t_max: ray.t_max - hit.t,
+      ..ray
     };
-    le + f * self.li(accel, sampler, new_ray, bounce + 1) * cosine
-    // wi.into()
-    // ((hit.ns + 1.0) * 0.5).into()
-    // hit.dpdu.into()
-    // Color::new(hit.uv.x, hit.uv.y, 0.0)
+  }
+}
+
+impl Integrator for VolumePathIntegrator {
+  fn li(&self, accel: &Accelerator, sampler: &mut dyn Sampler, ray: Ray, bounce: u32) -> Color {
+    let lights = accel.lights();
+    let mut path = PathState::new(ray, bounce);
+    let mut medium = accel.medium();
+    // Media take a varying number of random values per bounce, so they draw them from a
+    // generator seeded once per path, which keeps the sampler dimensions aligned
+    let mut rng = Pcg32::default();
+    rng.set_sequence(sampler.sample_seed(), 0);
+
+    loop {
+      let mut hit = Hit::default();
+      let found = accel.intersect(&path.ray, &mut hit);
+      let mut interaction = None;
+      if let Some(medium) = medium {
+        let t_max = if found { hit.t } else { f32::INFINITY };
+        let sample = medium.sample(&path.ray, t_max, &mut rng);
+        path.radiance = path.radiance + path.throughput * sample.emission;
+        path.throughput = path.throughput * sample.weight;
+        interaction = sample.interaction;
+      }
+      if path.throughput.is_black() {
+        break;
+      }
+
+      // Scattering inside the medium
+      if let Some(interaction) = interaction {
+        if path.bounce >= self.max_bounce {
+          break;
+        }
+        let wo = -path.ray.direction;
+        let light_select = sampler.get_1d();
+        let light_sample = sampler.get_2d();
+        let phase_sample = sampler.get_2d();
+        let rr_sample = sampler.get_1d();
+
+        if !lights.is_empty() {
+          let index = ((light_select * lights.len() as f32) as usize).min(lights.len() - 1);
+          let light = &lights[index];
+          if let Some(ls) = light.sample_li(interaction.p, path.ray.time, &light_sample) {
+            let light_pdf = ls.pdf / lights.len() as f32;
+            let phase = interaction.phase.p(wo, ls.wi);
+            if phase > 0.0 && light_pdf > 0.0 {
+              let shadow_ray = Ray {
+                origin: interaction.p,
+                direction: ls.wi,
+                t_min: 0.0,
+                t_max: ls.distance - 0.001,
+                time: path.ray.time,
+              };
+              let tr = transmittance(accel, &shadow_ray, medium, &mut rng);
+              let weight = if light.is_delta() {
+                1.0
+              } else {
+                power_heuristic(light_pdf, phase)
+              };
+              path.radiance =
+                path.radiance + path.throughput * tr * ls.li * (phase * weight / light_pdf);
+            }
+          }
+        }
+
+        // the phase function is sampled exactly, so the throughput stays the same
+        let (wi, pdf) = interaction.phase.sample_p(wo, &phase_sample);
+        path.bsdf_pdf = pdf;
+        path.specular_bounce = false;
+        if !path.survive(self.rr_threshold, rr_sample) {
+          break;
+        }
+        path.spawn(interaction.p, wi, 0.0);
+        continue;
+      }
+
+      if !found {
+        path.escape(accel);
+        break;
+      }
+      // Medium boundaries without a surface are crossed without counting as a bounce
+      if hit.material.is_some_and(|material| material.is_interface()) {
+        medium = next_medium(accel, &hit, path.ray.direction, medium);
+        path.pass_through(&hit);
+        continue;
+      }
+      // shadow rays start in the medium on the side of the surface they leave towards
+      let visibility = |hit: &Hit, shadow_ray: &Ray| {
+        let medium = next_medium(accel, hit, shadow_ray.direction, medium);
+        transmittance(accel, shadow_ray, medium, &mut rng)
+      };
+      if !path.scatter_surface(
+        accel,
+        sampler,
+        &mut hit,
+        self.max_bounce,
+        self.rr_threshold,
+        visibility,
+      ) {
+        break;
+      }
+      medium = next_medium(accel, &hit, path.ray.direction, medium);
+    }
+    path.radiance
   }
 }
@@ -0,0 +1,157 @@
+use super::film::Film;
+use instant::{Duration, Instant};
+use std::{
+  sync::{
+    atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
+    Arc, Mutex,
+  },
+  thread::JoinHandle,
+};
+
+/// Called on the render thread after each completed pass, with the film already resolved.
+pub type PassCallback = Box<dyn FnMut(&RenderProgress, &Film) + Send>;
+
+/// Snapshot of how far a render job has come.
+#[derive(Clone, Copy, Debug)]
+pub struct RenderProgress {
+  pub completed_passes: u32,
+  pub total_passes: u32,
+  /// Tiles finished over all passes so far.
+  pub completed_tiles: usize,
+  pub total_tiles: usize,
+  pub elapsed: Duration,
+  /// Estimated time until completion, once at least one tile has finished.
+  pub remaining: Option<Duration>,
+}
+
+impl RenderProgress {
+  /// Fraction of the work done, in $[0,1]$.
+  pub fn fraction(&self) -> f32 {
+    if self.total_tiles == 0 {
+      1.0
+    } else {
+      self.completed_tiles as f32 / self.total_tiles as f32
+    }
+  }
+}
+
+/// State shared between a [`RenderJob`] and the threads doing the work.
+pub(super) struct JobState {
+  cancelled: AtomicBool,
+  finished: AtomicBool,
+  completed_passes: AtomicU32,
+  completed_tiles: AtomicUsize,
+  total_passes: u32,
+  tiles_per_pass: usize,
+  started: Instant,
+  finish_time: Mutex<Option<Duration>>,
+}
+
+impl JobState {
+  pub fn new(total_passes: u32, tiles_per_pass: usize) -> Self {
+    Self {
+      cancelled: AtomicBool::new(false),
+      finished: AtomicBool::new(false),
+      completed_passes: AtomicU32::new(0),
+      completed_tiles: AtomicUsize::new(0),
+      total_passes,
+      tiles_per_pass,
+      started: Instant::now(),
+      finish_time: Mutex::new(None),
+    }
+  }
+
+  pub fn cancel(&self) {
+    self.cancelled.store(true, Ordering::Relaxed);
+  }
+
+  pub fn is_cancelled(&self) -> bool {
+    self.cancelled.load(Ordering::Relaxed)
+  }
+
+  pub fn complete_tile(&self) {
+    self.completed_tiles.fetch_add(1, Ordering::Relaxed);
+  }
+
+  pub fn complete_pass(&self) {
+    self.completed_passes.fetch_add(1, Ordering::Relaxed);
+  }
+
+  pub fn finish(&self) {
+    *self.finish_time.lock().unwrap() = Some(self.started.elapsed());
+    self.finished.store(true, Ordering::Release);
+  }
+
+  pub fn progress(&self) -> RenderProgress {
+    let elapsed = self
+      .finish_time
+      .lock()
+      .unwrap()
+      .unwrap_or_else(|| self.started.elapsed());
+    let completed_tiles = self.completed_tiles.load(Ordering::Relaxed);
+    let total_tiles = self.total_passes as usize * self.tiles_per_pass;
+    let remaining = if self.finished.load(Ordering::Acquire) {
+      Some(Duration::ZERO)
+    } else if completed_tiles > 0 {
+      let left = total_tiles.saturating_sub(completed_tiles) as f64;
+      Some(elapsed.mul_f64(left / completed_tiles as f64))
+    } else {
+      None
+    };
+    RenderProgress {
+      completed_passes: self.completed_passes.load(Ordering::Relaxed),
+      total_passes: self.total_passes,
+      completed_tiles,
+      total_tiles,
+      elapsed,
+      remaining,
+    }
+  }
+}
+
+/// Handle to a frame being rendered in the background.
+///
+/// Dropping the handle detaches the job; it keeps rendering into the film until it completes, or
+/// until the engine starts another render, which cancels it.
+pub struct RenderJob {
+  state: Arc<JobState>,
+  handle: JoinHandle<()>,
+}
+
+impl RenderJob {
+  pub(super) fn new(state: Arc<JobState>, handle: JoinHandle<()>) -> Self {
+    Self { state, handle }
+  }
+
+  pub fn progress(&self) -> RenderProgress {
+    self.state.progress()
+  }
+
+  pub fn elapsed(&self) -> Duration {
+    self.progress().elapsed
+  }
+
+  pub fn remaining(&self) -> Option<Duration> {
+    self.progress().remaining
+  }
+
+  /// Ask the workers to stop. Tiles already in flight are still finished and merged, unless a
+  /// newer render has cleared the film in the meantime.
+  pub fn cancel(&self) {
+    self.state.cancel();
+  }
+
+  pub fn is_cancelled(&self) -> bool {
+    self.state.is_cancelled()
+  }
+
+  pub fn is_finished(&self) -> bool {
+    self.state.finished.load(Ordering::Acquire)
+  }
+
+  /// Block until the job has completed or has been cancelled.
+  pub fn wait(self) -> RenderProgress {
+    self.handle.join().expect("Render thread panicked");
+    self.state.progress()
+  }
+}
@@ -0,0 +1,146 @@
+use super::{
+  environment::EnvironmentMap, material::Material, shape::Shape, transform::AnimatedTransform,
+};
+use crate::math::Color;
+use glam::{Vec2, Vec3A};
+use std::sync::Arc;
+
+pub(super) struct LightSample {
+  pub wi: Vec3A,
+  pub li: Color,
+  /// Solid angle density of `wi` as seen from the reference point.
+  pub pdf: f32,
+  pub distance: f32,
+}
+
+#[derive(Clone)]
+pub(super) enum Light {
+  Area {
+    shape: Shape,
+    transform: Arc<AnimatedTransform>,
+    /// Material of the emitting surface, whose emission is evaluated where it is sampled.
+    material: Arc<Material>,
+  },
+  Point {
+    position: Vec3A,
+    intensity: Color,
+  },
+  Spot {
+    position: Vec3A,
+    direction: Vec3A,
+    intensity: Color,
+    cos_inner: f32,
+    cos_outer: f32,
+  },
+  Directional {
+    direction: Vec3A,
+    radiance: Color,
+  },
+  Environment(Arc<EnvironmentMap>),
+}
+
+impl Light {
+  /// Delta lights cannot be hit by rays, so they are only reachable through light sampling.
+  pub fn is_delta(&self) -> bool {
+    !matches!(self, Light::Area { .. } | Light::Environment(_))
+  }
+
+  /// Sample incident light at `p` for a ray at `time`.
+  pub fn sample_li(&self, p: Vec3A, time: f32, u: &Vec2) -> Option<LightSample> {
+    match self {
+      Light::Point {
+        position,
+        intensity,
+      } => {
+        let (wi, distance) = direction_to(p, *position)?;
+        Some(LightSample {
+          wi,
+          li: *intensity / (distance * distance),
+          pdf: 1.0,
+          distance,
+        })
+      }
+      Light::Spot {
+        position,
+        direction,
+        intensity,
+        cos_inner,
+        cos_outer,
+      } => {
+        let (wi, distance) = direction_to(p, *position)?;
+        let falloff = smoothstep(*cos_outer, *cos_inner, direction.dot(-wi));
+        if falloff == 0.0 {
+          return None;
+        }
+        Some(LightSample {
+          wi,
+          li: *intensity * (falloff / (distance * distance)),
+          pdf: 1.0,
+          distance,
+        })
+      }
+      Light::Directional {
+        direction,
+        radiance,
+      } => Some(LightSample {
+        wi: -*direction,
+        li: *radiance,
+        pdf: 1.0,
+        distance: f32::INFINITY,
+      }),
+      Light::Environment(map) => {
+        let (wi, li, pdf) = map.sample(u)?;
+        Some(LightSample {
+          wi,
+          li,
+          pdf,
+          distance: f32::INFINITY,
+        })
+      }
+      Light::Area {
+        shape,
+        transform,
+        material,
+      } => {
+        let (object_to_world, world_to_object) = transform.interpolate_with_inverse(time);
+        let mut hit = shape.sample(u);
+        hit.transform(object_to_world, &world_to_object);
+        let (wi, distance) = direction_to(p, hit.p)?;
+        // Area lights only emit from their front side
+        let cos_light = hit.ng.dot(-wi);
+        if cos_light <= 0.0 {
+          return None;
+        }
+        Some(LightSample {
+          wi,
+          li: material.emission(&hit),
+          pdf: area_to_solid_angle_pdf(1.0 / shape.area(&object_to_world), distance, cos_light),
+          distance,
+        })
+      }
+    }
+  }
+}
+
+fn direction_to(p: Vec3A, q: Vec3A) -> Option<(Vec3A, f32)> {
+  let d = q - p;
+  let distance = d.length();
+  if distance == 0.0 {
+    None
+  } else {
+    Some((d / distance, distance))
+  }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+  if edge0 == edge1 {
+    return if x < edge0 { 0.0 } else { 1.0 };
+  }
+  let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+  t * t * (3.0 - 2.0 * t)
+}
+
+/// Convert a density with respect to surface area into one with respect to solid angle.
+pub(super) fn area_to_solid_angle_pdf(pdf_area: f32, distance: f32, cos_light: f32) -> f32 {
+  pdf_area * distance * distance / cos_light.abs()
+}
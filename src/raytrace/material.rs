@@ -0,0 +1,198 @@
+use super::{
+  bsdf::{Conductor, Dielectric, Interface, Lambertian, Principled, BSDF},
+  hit::Hit,
+  medium::Medium,
+  texture::{create_texture, FloatTexture, Texture},
+};
+use crate::{math::Color, prefabs};
+use glam::{Vec2, Vec3A};
+use std::sync::Arc;
+
+/// Surface parameters of a material, textured unless constant.
+enum Surface {
+  Lambertian {
+    albedo: Box<dyn Texture>,
+  },
+  Dielectric {
+    ior: FloatTexture,
+    roughness: FloatTexture,
+  },
+  Conductor {
+    eta: Box<dyn Texture>,
+    k: Box<dyn Texture>,
+    roughness_u: FloatTexture,
+    roughness_v: FloatTexture,
+  },
+  Principled(prefabs::Principled<Box<dyn Texture>, FloatTexture>),
+  Interface,
+}
+
+enum NormalMapping {
+  Normal {
+    texture: Box<dyn Texture>,
+    scale: f32,
+  },
+  Bump {
+    height: FloatTexture,
+    scale: f32,
+  },
+}
+
+/// Offset in texture space of the finite differences of bump maps.
+const BUMP_DELTA: f32 = 5e-4;
+
+pub(super) struct Material {
+  surface: Surface,
+  normal_mapping: Option<NormalMapping>,
+  emission: Box<dyn Texture>,
+  /// Medium filling the inside of the closed surface.
+  pub interior: Option<Arc<dyn Medium>>,
+}
+
+impl Material {
+  pub fn is_emissive(&self) -> bool {
+    self.emission.constant() != Some(Color::BLACK)
+  }
+  /// Radiance leaving the front side of the surface at `hit`.
+  pub fn emission(&self, hit: &Hit) -> Color {
+    self.emission.evaluate(hit)
+  }
+  /// Whether light may pass through the surface anywhere, so that it must be intersected from
+  /// both sides.
+  pub fn is_transmissive(&self) -> bool {
+    match &self.surface {
+      Surface::Lambertian { .. } | Surface::Conductor { .. } => false,
+      Surface::Dielectric { .. } | Surface::Interface => true,
+      Surface::Principled(params) => {
+        params.metallic.constant() != Some(1.0) && params.transmission.constant() != Some(0.0)
+      }
+    }
+  }
+  /// Whether the surface is invisible and only bounds a medium.
+  pub fn is_interface(&self) -> bool {
+    matches!(self.surface, Surface::Interface)
+  }
+  /// Perturb the shading normal of `hit` with the normal or bump map of the material, if any.
+  pub fn perturb_normal(&self, hit: &mut Hit) {
+    let ns = match &self.normal_mapping {
+      None => return,
+      Some(NormalMapping::Normal { texture, scale }) => {
+        let c = texture.evaluate(hit);
+        let n = Vec3A::new(2.0 * c.r - 1.0, 2.0 * c.g - 1.0, 2.0 * c.b - 1.0);
+        let n = Vec3A::new(n.x * scale, n.y * scale, n.z);
+        match n.try_normalize() {
+          Some(n) => hit.local_to_world(n),
+          None => return,
+        }
+      }
+      Some(NormalMapping::Bump { height, scale }) => {
+        // displace the neighbouring points along u and v, ignoring the change of the normal
+        let displace = |hit: &Hit| scale * height.evaluate(hit);
+        let d = displace(hit);
+        let du = displace(&Hit {
+          p: hit.p + BUMP_DELTA * hit.dpdu,
+          uv: hit.uv + Vec2::new(BUMP_DELTA, 0.0),
+          ..*hit
+        });
+        let dv = displace(&Hit {
+          p: hit.p + BUMP_DELTA * hit.dpdv,
+          uv: hit.uv + Vec2::new(0.0, BUMP_DELTA),
+          ..*hit
+        });
+        let dpdu = hit.dpdu + (du - d) / BUMP_DELTA * hit.ns;
+        let dpdv = hit.dpdv + (dv - d) / BUMP_DELTA * hit.ns;
+        let n = dpdu.cross(dpdv);
+        let n = match n.try_normalize() {
+          Some(n) => n,
+          None => return,
+        };
+        hit.dpdu = dpdu;
+        hit.dpdv = dpdv;
+        if n.dot(hit.ns) < 0.0 {
+          -n
+        } else {
+          n
+        }
+      }
+    };
+    hit.set_shading_normal(ns);
+  }
+  /// The BSDF with the parameters of the material at `hit`.
+  pub fn bsdf(&self, hit: &Hit) -> Box<dyn BSDF> {
+    match &self.surface {
+      Surface::Lambertian { albedo } => Box::new(Lambertian::new(albedo.evaluate(hit))),
+      Surface::Dielectric { ior, roughness } => {
+        Box::new(Dielectric::new(ior.evaluate(hit), roughness.evaluate(hit)))
+      }
+      Surface::Conductor {
+        eta,
+        k,
+        roughness_u,
+        roughness_v,
+      } => Box::new(Conductor::new(
+        eta.evaluate(hit),
+        k.evaluate(hit),
+        roughness_u.evaluate(hit),
+        roughness_v.evaluate(hit),
+      )),
+      Surface::Principled(params) => Box::new(Principled::new(
+        &params.map(|c| c.evaluate(hit), |f| f.evaluate(hit)),
+      )),
+      Surface::Interface => Box::new(Interface),
+    }
+  }
+}
+
+impl Default for Material {
+  fn default() -> Self {
+    Self::from(&prefabs::Material::default())
+  }
+}
+
+impl From<&prefabs::Material> for Material {
+  fn from(material: &prefabs::Material) -> Self {
+    let surface = match &material.bsdf {
+      prefabs::Bsdf::Lambertian { albedo } => Surface::Lambertian {
+        albedo: create_texture(albedo),
+      },
+      prefabs::Bsdf::Dielectric { ior, roughness } => Surface::Dielectric {
+        ior: ior.into(),
+        roughness: roughness.into(),
+      },
+      prefabs::Bsdf::Conductor {
+        eta,
+        k,
+        roughness_u,
+        roughness_v,
+      } => Surface::Conductor {
+        eta: create_texture(eta),
+        k: create_texture(k),
+        roughness_u: roughness_u.into(),
+        roughness_v: roughness_v.into(),
+      },
+      prefabs::Bsdf::Principled(principled) => {
+        Surface::Principled(principled.map(create_texture, |f| f.into()))
+      }
+      prefabs::Bsdf::Interface => Surface::Interface,
+    };
+    let normal_mapping = material
+      .normal_mapping
+      .as_ref()
+      .map(|mapping| match mapping {
+        prefabs::NormalMapping::Normal { texture, scale } => NormalMapping::Normal {
+          texture: create_texture(texture),
+          scale: *scale,
+        },
+        prefabs::NormalMapping::Bump { height, scale } => NormalMapping::Bump {
+          height: height.into(),
+          scale: *scale,
+        },
+      });
+    Self {
+      surface,
+      normal_mapping,
+      emission: create_texture(&material.emission),
+      interior: None,
+    }
+  }
+}
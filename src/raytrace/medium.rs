@@ -0,0 +1,139 @@
+use super::rng::Pcg32;
+use crate::{
+  math::{Color, Ray},
+  prefabs,
+};
+use glam::{Vec2, Vec3A};
+use std::f32::consts::PI;
+
+/// Henyey-Greenstein phase function. Positive asymmetry `g` scatters forward, negative
+/// backward, and zero scatters the same in every direction.
+#[derive(Clone, Copy, Debug)]
+pub(super) struct HenyeyGreenstein {
+  g: f32,
+}
+
+impl HenyeyGreenstein {
+  pub fn new(g: f32) -> Self {
+    Self {
+      g: g.clamp(-0.99, 0.99),
+    }
+  }
+
+  fn phase(&self, cos_theta: f32) -> f32 {
+    let denominator = 1.0 + self.g * self.g + 2.0 * self.g * cos_theta;
+    (1.0 - self.g * self.g) / (4.0 * PI * denominator * denominator.max(0.0).sqrt())
+  }
+
+  /// Density of scattering light arriving along `wi` towards `wo`. Both directions point away
+  /// from the scattering point, and the phase function is its own sampling pdf.
+  pub fn p(&self, wo: Vec3A, wi: Vec3A) -> f32 {
+    self.phase(wo.dot(wi))
+  }
+
+  /// Sample an incident direction for the outgoing direction `wo`, returning it with its pdf.
+  pub fn sample_p(&self, wo: Vec3A, u: &Vec2) -> (Vec3A, f32) {
+    let g = self.g;
+    let cos_theta = if g.abs() < 1e-3 {
+      1.0 - 2.0 * u.x
+    } else {
+      let sqr_term = (1.0 - g * g) / (1.0 + g - 2.0 * g * u.x);
+      -(1.0 + g * g - sqr_term * sqr_term) / (2.0 * g)
+    };
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * u.y;
+    let (v1, v2) = wo.any_orthonormal_pair();
+    let wi = sin_theta * phi.cos() * v1 + sin_theta * phi.sin() * v2 + cos_theta * wo;
+    (wi, self.phase(cos_theta))
+  }
+}
+
+/// Point inside a medium where a ray scattered.
+pub(super) struct MediumInteraction {
+  pub p: Vec3A,
+  pub phase: HenyeyGreenstein,
+}
+
+/// Outcome of sampling the next interaction along a ray.
+pub(super) struct MediumSample {
+  /// Weight to apply to the path throughput.
+  pub weight: Color,
+  /// Radiance emitted by the medium along the ray, to weight by the throughput before `weight`.
+  pub emission: Color,
+  /// Where the ray scattered, if it did before reaching its end.
+  pub interaction: Option<MediumInteraction>,
+}
+
+/// Participating medium that absorbs and scatters light along rays passing through it.
+pub(super) trait Medium: Send + Sync {
+  /// Transmittance between the origin of `ray` and the distance `t_max` along it.
+  fn tr(&self, ray: &Ray, t_max: f32, rng: &mut Pcg32) -> Color;
+  /// Sample the next interaction along `ray` before `t_max`.
+  fn sample(&self, ray: &Ray, t_max: f32, rng: &mut Pcg32) -> MediumSample;
+}
+
+fn exp(c: Color) -> Color {
+  Color::new(c.r.exp(), c.g.exp(), c.b.exp())
+}
+
+/// Medium with the same absorption and scattering everywhere.
+pub(super) struct HomogeneousMedium {
+  sigma_s: Color,
+  sigma_t: Color,
+  phase: HenyeyGreenstein,
+}
+
+impl HomogeneousMedium {
+  pub fn new(sigma_a: Color, sigma_s: Color, g: f32) -> Self {
+    Self {
+      sigma_s,
+      sigma_t: sigma_a + sigma_s,
+      phase: HenyeyGreenstein::new(g),
+    }
+  }
+}
+
+impl Medium for HomogeneousMedium {
+  fn tr(&self, ray: &Ray, t_max: f32, _rng: &mut Pcg32) -> Color {
+    let distance = (t_max * ray.direction.length()).min(f32::MAX);
+    exp(self.sigma_t * -distance)
+  }
+
+  fn sample(&self, ray: &Ray, t_max: f32, rng: &mut Pcg32) -> MediumSample {
+    // sample a distance with the extinction of one channel, weighted over all of them
+    let channel = ((rng.uniform_f32() * 3.0) as usize).min(2);
+    let sigma_t = [self.sigma_t.r, self.sigma_t.g, self.sigma_t.b][channel];
+    let length = ray.direction.length();
+    let distance = -(1.0 - rng.uniform_f32()).ln() / sigma_t;
+    let t = (distance / length).min(t_max);
+    let scattered = t < t_max;
+    let tr = exp(self.sigma_t * -(t * length).min(f32::MAX));
+    let density = if scattered { self.sigma_t * tr } else { tr };
+    let mut pdf = (density.r + density.g + density.b) / 3.0;
+    if pdf == 0.0 {
+      pdf = 1.0;
+    }
+    if scattered {
+      MediumSample {
+        weight: tr * self.sigma_s / pdf,
+        emission: Color::BLACK,
+        interaction: Some(MediumInteraction {
+          p: ray.origin + t * ray.direction,
+          phase: self.phase,
+        }),
+      }
+    } else {
+      MediumSample {
+        weight: tr / pdf,
+        emission: Color::BLACK,
+        interaction: None,
+      }
+    }
+  }
+}
+
+impl From<&prefabs::Medium> for HomogeneousMedium {
+  fn from(medium: &prefabs::Medium) -> Self {
+    Self::new(medium.sigma_a, medium.sigma_s, medium.g)
+  }
+}
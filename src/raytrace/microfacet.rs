@@ -0,0 +1,194 @@
+use glam::{Vec2, Vec3A};
+use std::f32::consts::PI;
+
+// Directions below are in the local shading frame, with the normal along +Z.
+
+pub(super) fn cos_theta(w: Vec3A) -> f32 {
+  w.z
+}
+
+fn cos2_theta(w: Vec3A) -> f32 {
+  w.z * w.z
+}
+
+fn sin2_theta(w: Vec3A) -> f32 {
+  (1.0 - cos2_theta(w)).max(0.0)
+}
+
+fn tan2_theta(w: Vec3A) -> f32 {
+  sin2_theta(w) / cos2_theta(w)
+}
+
+fn cos_phi(w: Vec3A) -> f32 {
+  let sin_theta = sin2_theta(w).sqrt();
+  if sin_theta == 0.0 {
+    1.0
+  } else {
+    (w.x / sin_theta).clamp(-1.0, 1.0)
+  }
+}
+
+fn sin_phi(w: Vec3A) -> f32 {
+  let sin_theta = sin2_theta(w).sqrt();
+  if sin_theta == 0.0 {
+    0.0
+  } else {
+    (w.y / sin_theta).clamp(-1.0, 1.0)
+  }
+}
+
+pub(super) fn same_hemisphere(w: Vec3A, wp: Vec3A) -> bool {
+  w.z * wp.z > 0.0
+}
+
+pub(super) fn reflect(wo: Vec3A, n: Vec3A) -> Vec3A {
+  -wo + 2.0 * wo.dot(n) * n
+}
+
+/// Refract `wi` through the interface with normal `n` and relative index of refraction `eta`
+/// (inside over outside). Returns the transmitted direction and the relative index of
+/// refraction seen along the path, or `None` on total internal reflection.
+pub(super) fn refract(wi: Vec3A, mut n: Vec3A, mut eta: f32) -> Option<(Vec3A, f32)> {
+  let mut cos_i = n.dot(wi);
+  // the ray leaves the inside of the surface
+  if cos_i < 0.0 {
+    eta = 1.0 / eta;
+    cos_i = -cos_i;
+    n = -n;
+  }
+  let sin2_i = (1.0 - cos_i * cos_i).max(0.0);
+  let sin2_t = sin2_i / (eta * eta);
+  if sin2_t >= 1.0 {
+    return None;
+  }
+  let cos_t = (1.0 - sin2_t).sqrt();
+  Some((-wi / eta + (cos_i / eta - cos_t) * n, eta))
+}
+
+/// Unpolarized Fresnel reflectance of a dielectric interface.
+pub(super) fn fresnel_dielectric(cos_i: f32, mut eta: f32) -> f32 {
+  let mut cos_i = cos_i.clamp(-1.0, 1.0);
+  if cos_i < 0.0 {
+    eta = 1.0 / eta;
+    cos_i = -cos_i;
+  }
+  let sin2_i = 1.0 - cos_i * cos_i;
+  let sin2_t = sin2_i / (eta * eta);
+  if sin2_t >= 1.0 {
+    return 1.0;
+  }
+  let cos_t = (1.0 - sin2_t).max(0.0).sqrt();
+  let r_parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+  let r_perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+  (r_parallel * r_parallel + r_perpendicular * r_perpendicular) / 2.0
+}
+
+/// Fresnel reflectance of a conductor with complex index of refraction `eta + i k`, relative
+/// to the outside medium.
+pub(super) fn fresnel_conductor(cos_i: f32, eta: f32, k: f32) -> f32 {
+  let cos_i = cos_i.clamp(0.0, 1.0);
+  let cos2 = cos_i * cos_i;
+  let sin2 = 1.0 - cos2;
+  let eta2 = eta * eta;
+  let k2 = k * k;
+  let t0 = eta2 - k2 - sin2;
+  let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+  let t1 = a2_plus_b2 + cos2;
+  let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+  let t2 = 2.0 * cos_i * a;
+  let rs = (t1 - t2) / (t1 + t2);
+  let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+  let t4 = t2 * sin2;
+  let rp = rs * (t3 - t4) / (t3 + t4);
+  0.5 * (rp + rs)
+}
+
+/// Anisotropic Trowbridge-Reitz (GGX) distribution of microfacet normals, sampled through its
+/// distribution of visible normals (Heitz 2018).
+#[derive(Clone, Copy, Debug)]
+pub(super) struct TrowbridgeReitz {
+  alpha_x: f32,
+  alpha_y: f32,
+}
+
+impl TrowbridgeReitz {
+  pub fn new(alpha_x: f32, alpha_y: f32) -> Self {
+    Self { alpha_x, alpha_y }
+  }
+
+  /// Map a perceptually linear roughness in $[0,1]$ to the alpha parameter.
+  pub fn roughness_to_alpha(roughness: f32) -> f32 {
+    roughness.max(0.0).sqrt()
+  }
+
+  /// Whether the surface is smooth enough to be treated as a perfect specular interface.
+  pub fn effectively_smooth(&self) -> bool {
+    self.alpha_x.max(self.alpha_y) < 1e-3
+  }
+
+  pub fn d(&self, wm: Vec3A) -> f32 {
+    let tan2 = tan2_theta(wm);
+    if tan2.is_infinite() {
+      return 0.0;
+    }
+    let cos4 = cos2_theta(wm) * cos2_theta(wm);
+    if cos4 < 1e-16 {
+      return 0.0;
+    }
+    let (cos_phi, sin_phi) = (cos_phi(wm), sin_phi(wm));
+    let e = tan2
+      * (cos_phi * cos_phi / (self.alpha_x * self.alpha_x)
+        + sin_phi * sin_phi / (self.alpha_y * self.alpha_y));
+    1.0 / (PI * self.alpha_x * self.alpha_y * cos4 * (1.0 + e) * (1.0 + e))
+  }
+
+  fn lambda(&self, w: Vec3A) -> f32 {
+    let tan2 = tan2_theta(w);
+    if tan2.is_infinite() {
+      return 0.0;
+    }
+    let (cos_phi, sin_phi) = (cos_phi(w), sin_phi(w));
+    let alpha2 = cos_phi * cos_phi * self.alpha_x * self.alpha_x
+      + sin_phi * sin_phi * self.alpha_y * self.alpha_y;
+    ((1.0 + alpha2 * tan2).sqrt() - 1.0) / 2.0
+  }
+
+  fn g1(&self, w: Vec3A) -> f32 {
+    1.0 / (1.0 + self.lambda(w))
+  }
+
+  /// Height-correlated masking and shadowing.
+  pub fn g(&self, wo: Vec3A, wi: Vec3A) -> f32 {
+    1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+  }
+
+  /// Density of microfacet normals `wm` visible from `w`.
+  pub fn pdf(&self, w: Vec3A, wm: Vec3A) -> f32 {
+    self.g1(w) / cos_theta(w).abs() * self.d(wm) * w.dot(wm).abs()
+  }
+
+  /// Sample a microfacet normal visible from `w`.
+  pub fn sample_wm(&self, w: Vec3A, u: &Vec2) -> Vec3A {
+    // transform to the hemisphere configuration of a unit roughness
+    let mut wh = Vec3A::new(self.alpha_x * w.x, self.alpha_y * w.y, w.z).normalize();
+    if wh.z < 0.0 {
+      wh = -wh;
+    }
+    let t1 = if wh.z < 0.99999 {
+      Vec3A::Z.cross(wh).normalize()
+    } else {
+      Vec3A::X
+    };
+    let t2 = wh.cross(t1);
+    // uniformly sample a disk, warped towards the visible half
+    let r = u.x.sqrt();
+    let theta = 2.0 * PI * u.y;
+    let mut p = Vec2::new(r * theta.cos(), r * theta.sin());
+    let h = (1.0 - p.x * p.x).sqrt();
+    let s = (1.0 + wh.z) / 2.0;
+    p.y = (1.0 - s) * h + s * p.y;
+    let pz = (1.0 - p.length_squared()).max(0.0).sqrt();
+    let nh = p.x * t1 + p.y * t2 + pz * wh;
+    Vec3A::new(self.alpha_x * nh.x, self.alpha_y * nh.y, nh.z.max(1e-6)).normalize()
+  }
+}
@@ -1,35 +1,81 @@
 mod accelerator;
 mod bsdf;
 mod camera;
+mod environment;
 mod film;
+mod filter;
 mod hit;
+mod imageio;
 mod integrator;
+mod job;
+mod light;
+mod material;
+mod medium;
+mod microfacet;
+mod rng;
 mod sampler;
 mod scene;
 mod shape;
+mod texture;
+mod tonemap;
+mod transform;
+mod volume;
 
+pub use self::environment::{EnvironmentMap, Sky};
+pub use self::film::{Film, RenderMetadata};
+pub use self::filter::Filter;
+pub use self::imageio::{ColorEncoding, Image, ImageError};
+pub use self::integrator::IntegratorKind;
+pub use self::job::{PassCallback, RenderJob, RenderProgress};
+pub use self::sampler::SamplerKind;
 pub use self::scene::SceneEngine;
+pub use self::tonemap::{DisplaySettings, ToneMapping};
+pub use self::volume::{VolumeError, VoxelGrid};
 use self::{
   accelerator::Accelerator,
   camera::{Camera, PinholeCamera},
-  film::Film,
-  integrator::{Integrator, PathIntegrator},
+  film::{tile_bounds, FilmTile},
+  job::JobState,
 };
 use crate::{
   core::Timer,
-  raytrace::sampler::{Sampler, StratifiedSampler},
+  math::{Color, Ray},
 };
-use glam::{Vec2, Vec3};
+use glam::{UVec2, Vec2};
 use std::{
-  sync::{Arc, RwLock, RwLockReadGuard, Weak},
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex, RwLock, Weak,
+  },
   thread,
 };
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct RenderSettings {
   pub resolution: (u32, u32),
   pub samples_per_pixel: u32,
   pub max_bounce: u32,
+  pub sampler: SamplerKind,
+  pub integrator: IntegratorKind,
+  pub filter: Filter,
+  /// Seed of every random decision made while rendering. The same seed and settings give the
+  /// same image, regardless of the number of threads and the tile size.
+  pub seed: u64,
+  /// Number of worker threads, or 0 to use every available core.
+  pub threads: usize,
+  /// Width and height of the square tiles handed to worker threads.
+  pub tile_size: u32,
+  pub display: DisplaySettings,
+}
+
+impl RenderSettings {
+  fn worker_count(&self) -> usize {
+    if self.threads > 0 {
+      self.threads
+    } else {
+      thread::available_parallelism().map_or(1, |n| n.get())
+    }
+  }
 }
 
 impl Default for RenderSettings {
@@ -38,6 +84,13 @@ impl Default for RenderSettings {
       resolution: (640, 480),
       samples_per_pixel: 64,
       max_bounce: 8,
+      sampler: SamplerKind::Sobol,
+      integrator: IntegratorKind::Path,
+      filter: Filter::default(),
+      seed: 0,
+      threads: 0,
+      tile_size: 32,
+      display: DisplaySettings::default(),
     }
   }
 }
@@ -45,12 +98,28 @@ impl Default for RenderSettings {
 pub struct RenderEngine {
   pub film: Arc<RwLock<Film>>,
   pub settings: RenderSettings,
+  /// The job last started, cancelled when the next one takes over the film.
+  active: Mutex<Option<Arc<JobState>>>,
 }
 
 pub struct RenderContext {
   settings: RenderSettings,
   accelerator: Arc<Accelerator>,
   camera: Weak<dyn Camera>,
+  /// Shutter open and close times of the camera.
+  shutter: (f32, f32),
+  on_pass: Option<PassCallback>,
+}
+
+impl RenderContext {
+  /// Invoke `callback` after every pass of the render, once the film display is up to date.
+  pub fn with_pass_callback<F>(mut self, callback: F) -> Self
+  where
+    F: FnMut(&RenderProgress, &Film) + Send + 'static,
+  {
+    self.on_pass = Some(Box::new(callback));
+    self
+  }
 }
 
 impl RenderEngine {
@@ -59,7 +128,11 @@ impl RenderEngine {
       settings.resolution.0,
       settings.resolution.1,
     )));
-    Self { film, settings }
+    Self {
+      film,
+      settings,
+      active: Mutex::new(None),
+    }
   }
   pub fn prepare_render(&mut self, scene: &SceneEngine) -> RenderContext {
     let timer = Timer::new();
@@ -67,50 +140,211 @@ impl RenderEngine {
     let accelerator = Arc::new(Accelerator::build(&scene));
     println!("BVH building took: {:?}", timer.elapsed());
 
-    let camera = Arc::downgrade(&scene.cameras[scene.active_cam]);
+    let (camera, shutter) = &scene.cameras[scene.active_cam];
+    let camera = Arc::downgrade(camera);
 
     RenderContext {
       settings: self.settings.clone(),
       accelerator,
       camera,
+      shutter: *shutter,
+      on_pass: None,
     }
   }
-  pub fn render_frame(&self, context: RenderContext) {
+  pub fn render_frame(&self, context: RenderContext) -> RenderJob {
     let width = context.settings.resolution.0;
     let height = context.settings.resolution.1;
     let film_handle = self.film.clone();
     let camera = context.camera.upgrade().expect("Camera no longer exists");
+    let tiles = tile_bounds(width, height, context.settings.tile_size);
+    let state = Arc::new(JobState::new(
+      context.settings.samples_per_pixel,
+      tiles.len(),
+    ));
+
+    if let Some(previous) = self.active.lock().unwrap().replace(state.clone()) {
+      previous.cancel();
+    }
+    // the previous job may still be finishing tiles, which it drops once it sees a new generation
+    let generation = film_handle.write().unwrap().clear();
 
-    thread::spawn(move || {
+    let job_state = state.clone();
+    let handle = thread::spawn(move || {
       let timer = Timer::new();
-      let integrator = PathIntegrator::new(context.settings.max_bounce);
-      let mut sampler = StratifiedSampler::new();
-      for spp in 1..=context.settings.samples_per_pixel {
-        for y in 0..height {
-          for x in 0..width {
-            let jitter = sampler.get_2d() - 0.5;
-            let ndc = Vec2::new(
-              (x as f32 + jitter.x) / (width - 1) as f32,
-              (y as f32 + jitter.y) / (height - 1) as f32,
-            ) * 2.0
-              - 1.0;
-            let ray = camera.ray(&ndc);
-            let color = integrator.li(&context.accelerator, &mut sampler, ray, 0);
-            {
-              let film_rw_lock = film_handle.clone();
-              let mut film = film_rw_lock.write().unwrap();
-              let acc_pixel = if spp == 1 {
-                color
-              } else {
-                let p = film.pixel(x, y);
-                p + (color - p) / spp as f32
-              };
-              film.write_pixel(x, y, acc_pixel.into());
-            }
+      let RenderContext {
+        settings,
+        accelerator,
+        shutter,
+        mut on_pass,
+        ..
+      } = context;
+      let state = &job_state;
+      let workers = settings.worker_count().min(tiles.len());
+      for pass in 0..settings.samples_per_pixel {
+        // Every pass renders each tile once; workers pull tiles until none are left
+        let next_tile = AtomicUsize::new(0);
+        thread::scope(|scope| {
+          for _ in 0..workers {
+            scope.spawn(|| {
+              let integrator = settings.integrator.create(settings.max_bounce);
+              let mut sampler = settings
+                .sampler
+                .create(settings.samples_per_pixel, settings.seed);
+              while !state.is_cancelled() {
+                let index = next_tile.fetch_add(1, Ordering::Relaxed);
+                let bounds = match tiles.get(index) {
+                  Some(bounds) => bounds,
+                  None => break,
+                };
+                let mut tile = FilmTile::new(*bounds);
+                for y in bounds.y0..bounds.y1 {
+                  for x in bounds.x0..bounds.x1 {
+                    sampler.start_pixel(UVec2::new(x, y));
+                    sampler.start_sample(pass);
+                    // film rows are stored bottom to top
+                    let offset = sampler.get_2d();
+                    let ndc = Vec2::new(x as f32 + offset.x, y as f32 + offset.y)
+                      / Vec2::new(width as f32, height as f32)
+                      * 2.0
+                      - 1.0;
+                    let lens = sampler.get_2d();
+                    let time = shutter.0 + (shutter.1 - shutter.0) * sampler.get_1d();
+                    let color = match camera.ray(&ndc, &lens) {
+                      Some((ray, weight)) => {
+                        let ray = Ray { time, ..ray };
+                        integrator.li(&accelerator, sampler.as_mut(), ray, 0) * weight
+                      }
+                      None => Color::BLACK,
+                    };
+                    tile.add_sample(x, y, offset, color);
+                  }
+                }
+                let mut film = film_handle.write().unwrap();
+                if film.generation() != generation {
+                  state.cancel();
+                  break;
+                }
+                film.merge_tile(&tile);
+                state.complete_tile();
+              }
+            });
+          }
+        });
+        {
+          let mut film = film_handle.write().unwrap();
+          if film.generation() != generation {
+            break;
+          }
+          film.splat_pass(&settings.filter, settings.worker_count());
+          film.update_display(&settings.display);
+        }
+        if state.is_cancelled() {
+          break;
+        }
+        state.complete_pass();
+        if let Some(on_pass) = on_pass.as_mut() {
+          let film = film_handle.read().unwrap();
+          if film.generation() != generation {
+            break;
           }
+          on_pass(&state.progress(), &film);
         }
       }
+      state.finish();
       println!("Full render took: {:?}", timer.elapsed());
     });
+    RenderJob::new(state, handle)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{
+    material::Material,
+    scene::{Node, Primitive},
+    transform::AnimatedTransform,
+    *,
+  };
+  use crate::prefabs;
+  use glam::{Affine3A, Vec3};
+
+  fn sphere(center: Vec3, radius: f32, material: prefabs::Material) -> Node {
+    let transform = Affine3A::from_translation(center);
+    Node {
+      prim: Primitive::Sphere(
+        radius,
+        Arc::new(AnimatedTransform::new(transform, transform)),
+        Arc::new(Material::from(&material)),
+      ),
+      children: Vec::new(),
+    }
+  }
+
+  fn render(settings: RenderSettings) -> Vec<Color> {
+    let mut scene = SceneEngine::new();
+    let grey = prefabs::Material::lambertian(Color::splat(0.5));
+    scene.root = Node {
+      prim: Primitive::Empty,
+      children: vec![
+        sphere(Vec3::new(0.0, -101.0, 0.0), 100.0, grey.clone()),
+        sphere(Vec3::ZERO, 1.0, grey),
+        sphere(
+          Vec3::new(1.5, 1.5, 1.0),
+          0.3,
+          prefabs::Material::lambertian(Color::BLACK).with_emission(Color::splat(10.0)),
+        ),
+      ],
+    };
+    let (width, height) = settings.resolution;
+    let camera = PinholeCamera::new(
+      45f32.to_radians(),
+      width as f32 / height as f32,
+      0.1,
+      100.0,
+      Affine3A::from_translation(Vec3::new(0.0, 0.0, 5.0)),
+    );
+    scene.cameras.push((Arc::new(camera), (0.0, 0.0)));
+
+    let mut engine = RenderEngine::new(settings);
+    let context = engine.prepare_render(&scene);
+    engine.render_frame(context).wait();
+    let film = engine.film.read().unwrap();
+    (0..height)
+      .flat_map(|y| (0..width).map(move |x| (x, y)))
+      .map(|(x, y)| film.pixel(x, y))
+      .collect()
+  }
+
+  #[test]
+  fn render_does_not_depend_on_threads_or_tiles() {
+    let settings = RenderSettings {
+      resolution: (24, 16),
+      samples_per_pixel: 4,
+      // a wide filter splats samples across tile borders
+      filter: Filter::Gaussian {
+        radius: 1.5,
+        sigma: 0.5,
+      },
+      seed: 9,
+      ..Default::default()
+    };
+    let single = render(RenderSettings {
+      threads: 1,
+      tile_size: 32,
+      ..settings.clone()
+    });
+    let split = render(RenderSettings {
+      threads: 4,
+      tile_size: 5,
+      ..settings.clone()
+    });
+    assert_eq!(single, split);
+    assert!(single.iter().any(|c| c.r > 0.0));
+
+    let reseeded = render(RenderSettings {
+      seed: 10,
+      ..settings
+    });
+    assert_ne!(single, reseeded);
   }
 }
@@ -0,0 +1,84 @@
+const PCG32_DEFAULT_STATE: u64 = 0x853c49e6748fea9b;
+const PCG32_DEFAULT_STREAM: u64 = 0xda3e39cb94b95bdb;
+const PCG32_MULT: u64 = 0x5851f42d4c957f2d;
+
+pub(super) const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+pub(super) fn mix_bits(mut v: u64) -> u64 {
+  v ^= v >> 31;
+  v = v.wrapping_mul(0x7fb5d329728ea185);
+  v ^= v >> 27;
+  v = v.wrapping_mul(0x81dadef4bc2dd44d);
+  v ^= v >> 33;
+  v
+}
+
+/// Combine a list of values into a single well mixed 64-bit hash.
+pub(super) fn hash(values: &[u64]) -> u64 {
+  values.iter().fold(0x9e3779b97f4a7c15, |h, v| {
+    mix_bits(h ^ v.wrapping_add(0x9e3779b97f4a7c15))
+  })
+}
+
+/// A uniform value in $[0,1)$ derived from the hash of `values`.
+pub(super) fn hash_float(values: &[u64]) -> f32 {
+  (hash(values) >> 40) as f32 / (1u64 << 24) as f32
+}
+
+/// PCG32 random number generator (O'Neill 2014). Every sequence index selects an independent
+/// stream, and the generator can jump ahead in constant time, so a pixel sample can find its
+/// own random numbers without depending on what was generated before it.
+#[derive(Clone)]
+pub(super) struct Pcg32 {
+  state: u64,
+  inc: u64,
+}
+
+impl Default for Pcg32 {
+  fn default() -> Self {
+    Self {
+      state: PCG32_DEFAULT_STATE,
+      inc: PCG32_DEFAULT_STREAM,
+    }
+  }
+}
+
+impl Pcg32 {
+  pub fn set_sequence(&mut self, sequence_index: u64, seed: u64) {
+    self.state = 0;
+    self.inc = (sequence_index << 1) | 1;
+    self.uniform_u32();
+    self.state = self.state.wrapping_add(seed);
+    self.uniform_u32();
+  }
+
+  pub fn uniform_u32(&mut self) -> u32 {
+    let old_state = self.state;
+    self.state = old_state.wrapping_mul(PCG32_MULT).wrapping_add(self.inc);
+    let xor_shifted = (((old_state >> 18) ^ old_state) >> 27) as u32;
+    let rot = (old_state >> 59) as u32;
+    xor_shifted.rotate_right(rot)
+  }
+
+  pub fn uniform_f32(&mut self) -> f32 {
+    (self.uniform_u32() as f32 / (1u64 << 32) as f32).min(ONE_MINUS_EPSILON)
+  }
+
+  /// Skip `delta` values ahead in the sequence.
+  pub fn advance(&mut self, mut delta: u64) {
+    let mut cur_mult = PCG32_MULT;
+    let mut cur_plus = self.inc;
+    let mut acc_mult = 1u64;
+    let mut acc_plus = 0u64;
+    while delta > 0 {
+      if delta & 1 != 0 {
+        acc_mult = acc_mult.wrapping_mul(cur_mult);
+        acc_plus = acc_plus.wrapping_mul(cur_mult).wrapping_add(cur_plus);
+      }
+      cur_plus = cur_mult.wrapping_add(1).wrapping_mul(cur_plus);
+      cur_mult = cur_mult.wrapping_mul(cur_mult);
+      delta >>= 1;
+    }
+    self.state = acc_mult.wrapping_mul(self.state).wrapping_add(acc_plus);
+  }
+}
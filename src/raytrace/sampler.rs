@@ -1,30 +1,480 @@
-use rand::Rng;
+use super::rng::{hash, hash_float, mix_bits, Pcg32, ONE_MINUS_EPSILON};
+use glam::{UVec2, Vec2};
 
+/// Source of sample values for one pixel sample at a time. Values are handed out dimension by
+/// dimension, so every consumer must request them in the same order for each sample.
 pub trait Sampler {
+  /// Begin generating samples for the given pixel.
+  fn start_pixel(&mut self, pixel: UVec2);
+  /// Begin the `index`-th sample of the current pixel, restarting at the first dimension.
+  fn start_sample(&mut self, index: u32);
   fn get_1d(&mut self) -> f32;
-  fn get_2d(&mut self) -> glam::Vec2;
+  fn get_2d(&mut self) -> Vec2;
+  /// Seed of the random values a sample needs beyond its dimensions, such as the varying number
+  /// drawn in media. It only depends on the render seed, the pixel and the sample index.
+  fn sample_seed(&self) -> u64;
 }
 
+fn sample_seed(seed: u64, pixel: UVec2, index: u32) -> u64 {
+  hash(&[pixel.x as u64, pixel.y as u64, index as u64, seed])
+}
+
+/// Which [`Sampler`] the render engine creates for its workers.
+#[derive(Clone, Copy, Debug)]
+pub enum SamplerKind {
+  Independent,
+  Stratified,
+  Halton,
+  Sobol,
+}
+
+impl SamplerKind {
+  pub(super) fn create(&self, samples_per_pixel: u32, seed: u64) -> Box<dyn Sampler> {
+    match self {
+      SamplerKind::Independent => Box::new(IndependentSampler::new(seed)),
+      SamplerKind::Stratified => Box::new(StratifiedSampler::new(samples_per_pixel, seed)),
+      SamplerKind::Halton => Box::new(HaltonSampler::new(seed)),
+      SamplerKind::Sobol => Box::new(SobolSampler::new(seed)),
+    }
+  }
+}
+
+/// Element `i` of a random permutation of `[0, l)` selected by `p` (Kensler 2013).
+fn permutation_element(mut i: u32, l: u32, p: u32) -> u32 {
+  let mut w = l - 1;
+  w |= w >> 1;
+  w |= w >> 2;
+  w |= w >> 4;
+  w |= w >> 8;
+  w |= w >> 16;
+  loop {
+    i ^= p;
+    i = i.wrapping_mul(0xe170893d);
+    i ^= p >> 16;
+    i ^= (i & w) >> 4;
+    i ^= p >> 8;
+    i = i.wrapping_mul(0x0929eb3f);
+    i ^= p >> 23;
+    i ^= (i & w) >> 1;
+    i = i.wrapping_mul(1 | p >> 27);
+    i = i.wrapping_mul(0x6935fa69);
+    i ^= (i & w) >> 11;
+    i = i.wrapping_mul(0x74dcb303);
+    i ^= (i & w) >> 2;
+    i = i.wrapping_mul(0x9e501cc3);
+    i ^= (i & w) >> 2;
+    i = i.wrapping_mul(0xc860a3df);
+    i &= w;
+    i ^= i >> 5;
+    if i < l {
+      break;
+    }
+  }
+  i.wrapping_add(p) % l
+}
+
+/// Uniform random values with no stratification across samples. Each pixel has its own PCG
+/// stream, and each sample starts at a fixed offset into it.
+pub struct IndependentSampler {
+  seed: u64,
+  rng: Pcg32,
+  pixel: UVec2,
+  sample_index: u32,
+}
+
+impl IndependentSampler {
+  pub fn new(seed: u64) -> Self {
+    Self {
+      seed,
+      rng: Pcg32::default(),
+      pixel: UVec2::ZERO,
+      sample_index: 0,
+    }
+  }
+}
+
+impl Sampler for IndependentSampler {
+  fn start_pixel(&mut self, pixel: UVec2) {
+    self.pixel = pixel;
+  }
+
+  fn start_sample(&mut self, index: u32) {
+    self.sample_index = index;
+    let sequence = hash(&[self.pixel.x as u64, self.pixel.y as u64, self.seed]);
+    self.rng.set_sequence(sequence, mix_bits(self.seed));
+    // leave room for 65536 dimensions per sample
+    self.rng.advance((index as u64) << 16);
+  }
+
+  fn get_1d(&mut self) -> f32 {
+    self.rng.uniform_f32()
+  }
+
+  fn get_2d(&mut self) -> Vec2 {
+    let x = self.rng.uniform_f32();
+    let y = self.rng.uniform_f32();
+    Vec2::new(x, y)
+  }
+
+  fn sample_seed(&self) -> u64 {
+    sample_seed(self.seed, self.pixel, self.sample_index)
+  }
+}
+
+/// Jittered stratification: each dimension of a pixel is split into `samples_per_pixel` strata
+/// (a grid of them for 2D requests) and every sample falls into a different one. Strata are
+/// visited in a random order per pixel and dimension, so that dimensions are decorrelated.
 pub struct StratifiedSampler {
   samples_per_pixel: u32,
-  rng: rand::rngs::ThreadRng,
+  seed: u64,
+  pixel: UVec2,
+  sample_index: u32,
+  dimension: u32,
 }
 
 impl StratifiedSampler {
-  pub fn new() -> Self {
+  pub fn new(samples_per_pixel: u32, seed: u64) -> Self {
     Self {
-      samples_per_pixel: 64,
-      rng: rand::thread_rng(),
+      samples_per_pixel: samples_per_pixel.max(1),
+      seed,
+      pixel: UVec2::ZERO,
+      sample_index: 0,
+      dimension: 0,
     }
   }
+
+  fn dimension_hash(&self) -> u64 {
+    hash(&[
+      self.pixel.x as u64,
+      self.pixel.y as u64,
+      self.dimension as u64,
+      self.seed,
+    ])
+  }
+
+  fn jitter(&self, axis: u64) -> f32 {
+    hash_float(&[
+      self.pixel.x as u64,
+      self.pixel.y as u64,
+      self.dimension as u64,
+      self.sample_index as u64,
+      self.seed,
+      axis,
+    ])
+  }
 }
 
 impl Sampler for StratifiedSampler {
+  fn start_pixel(&mut self, pixel: UVec2) {
+    self.pixel = pixel;
+  }
+
+  fn start_sample(&mut self, index: u32) {
+    self.sample_index = index;
+    self.dimension = 0;
+  }
+
   fn get_1d(&mut self) -> f32 {
-    self.rng.gen()
+    let strata = self.samples_per_pixel;
+    let stratum = permutation_element(
+      self.sample_index % strata,
+      strata,
+      self.dimension_hash() as u32,
+    );
+    let value = (stratum as f32 + self.jitter(0)) / strata as f32;
+    self.dimension += 1;
+    value.min(ONE_MINUS_EPSILON)
+  }
+
+  fn get_2d(&mut self) -> Vec2 {
+    let nx = (self.samples_per_pixel as f32).sqrt().ceil() as u32;
+    let ny = self.samples_per_pixel.div_ceil(nx);
+    let strata = nx * ny;
+    let stratum = permutation_element(
+      self.sample_index % strata,
+      strata,
+      self.dimension_hash() as u32,
+    );
+    let value = Vec2::new(
+      ((stratum % nx) as f32 + self.jitter(0)) / nx as f32,
+      ((stratum / nx) as f32 + self.jitter(1)) / ny as f32,
+    );
+    self.dimension += 2;
+    value.min(Vec2::splat(ONE_MINUS_EPSILON))
+  }
+
+  fn sample_seed(&self) -> u64 {
+    sample_seed(self.seed, self.pixel, self.sample_index)
+  }
+}
+
+const PRIMES: [u32; 64] = [
+  2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+  101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193,
+  197, 199, 211, 223, 227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307,
+  311,
+];
+
+/// Radical inverse of `a` in the given base, with the digits Owen scrambled by `hash`.
+fn owen_scrambled_radical_inverse(base: u32, mut a: u64, hash: u64) -> f32 {
+  let base64 = base as u64;
+  let inv_base = 1.0 / base as f32;
+  let mut inv_base_m = 1.0f32;
+  let mut reversed_digits = 0u64;
+  // stop once further digits no longer change the floating point result
+  while 1.0 - (base - 1) as f32 * inv_base_m < 1.0 {
+    let next = a / base64;
+    let digit = (a - next * base64) as u32;
+    // the permutation of each digit depends on all the digits before it
+    let digit_hash = mix_bits(hash ^ reversed_digits) as u32;
+    let digit = permutation_element(digit, base, digit_hash);
+    reversed_digits = reversed_digits * base64 + digit as u64;
+    inv_base_m *= inv_base;
+    a = next;
+  }
+  (inv_base_m * reversed_digits as f32).min(ONE_MINUS_EPSILON)
+}
+
+/// Halton sequence using one prime base per dimension, Owen scrambled independently for every
+/// pixel and dimension.
+pub struct HaltonSampler {
+  seed: u64,
+  pixel: UVec2,
+  sample_index: u32,
+  dimension: u32,
+}
+
+impl HaltonSampler {
+  pub fn new(seed: u64) -> Self {
+    Self {
+      seed,
+      pixel: UVec2::ZERO,
+      sample_index: 0,
+      dimension: 0,
+    }
+  }
+
+  fn next(&mut self) -> f32 {
+    // bases are reused past the table, with a different scramble each time around
+    let base = PRIMES[self.dimension as usize % PRIMES.len()];
+    let scramble = hash(&[
+      self.pixel.x as u64,
+      self.pixel.y as u64,
+      self.dimension as u64,
+      self.seed,
+    ]);
+    self.dimension += 1;
+    owen_scrambled_radical_inverse(base, self.sample_index as u64, scramble)
+  }
+}
+
+impl Sampler for HaltonSampler {
+  fn start_pixel(&mut self, pixel: UVec2) {
+    self.pixel = pixel;
+  }
+
+  fn start_sample(&mut self, index: u32) {
+    self.sample_index = index;
+    self.dimension = 0;
+  }
+
+  fn get_1d(&mut self) -> f32 {
+    self.next()
+  }
+
+  fn get_2d(&mut self) -> Vec2 {
+    let x = self.next();
+    let y = self.next();
+    Vec2::new(x, y)
+  }
+
+  fn sample_seed(&self) -> u64 {
+    sample_seed(self.seed, self.pixel, self.sample_index)
+  }
+}
+
+/// First two dimensions of the Sobol sequence as 32-bit fixed point values.
+fn sobol(index: u32, dimension: usize) -> u32 {
+  let mut v = 1u32 << 31;
+  let mut x = 0;
+  let mut index = index;
+  while index != 0 {
+    if index & 1 != 0 {
+      x ^= v;
+    }
+    // dimension 0 is van der Corput, dimension 1 uses the primitive polynomial x + 1
+    v = if dimension == 0 { v >> 1 } else { v ^ (v >> 1) };
+    index >>= 1;
+  }
+  x
+}
+
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+  x = x.wrapping_add(seed);
+  x ^= x.wrapping_mul(0x6c50b47c);
+  x ^= x.wrapping_mul(0xb82f1e52);
+  x ^= x.wrapping_mul(0xc7afe638);
+  x ^= x.wrapping_mul(0x8d22f6e6);
+  x
+}
+
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+  laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+fn to_unit_float(x: u32) -> f32 {
+  (x as f32 / (1u64 << 32) as f32).min(ONE_MINUS_EPSILON)
+}
+
+/// Owen-scrambled Sobol sampler (Burley 2020). Every 1D or 2D request draws from the first
+/// Sobol dimensions with its own index shuffle and scramble, seeded per pixel and dimension.
+pub struct SobolSampler {
+  seed: u64,
+  pixel: UVec2,
+  sample_index: u32,
+  dimension: u32,
+}
+
+impl SobolSampler {
+  pub fn new(seed: u64) -> Self {
+    Self {
+      seed,
+      pixel: UVec2::ZERO,
+      sample_index: 0,
+      dimension: 0,
+    }
+  }
+
+  fn dimension_seed(&self) -> u64 {
+    hash(&[
+      self.pixel.x as u64,
+      self.pixel.y as u64,
+      self.dimension as u64,
+      self.seed,
+    ])
+  }
+}
+
+impl Sampler for SobolSampler {
+  fn start_pixel(&mut self, pixel: UVec2) {
+    self.pixel = pixel;
+  }
+
+  fn start_sample(&mut self, index: u32) {
+    self.sample_index = index;
+    self.dimension = 0;
+  }
+
+  fn get_1d(&mut self) -> f32 {
+    let seed = self.dimension_seed();
+    self.dimension += 1;
+    let index = nested_uniform_scramble(self.sample_index, seed as u32);
+    to_unit_float(nested_uniform_scramble(
+      sobol(index, 0),
+      (seed >> 32) as u32,
+    ))
+  }
+
+  fn get_2d(&mut self) -> Vec2 {
+    let seed = self.dimension_seed();
+    self.dimension += 2;
+    let index = nested_uniform_scramble(self.sample_index, seed as u32);
+    let x_seed = mix_bits(seed ^ 1);
+    let y_seed = mix_bits(seed ^ 2);
+    Vec2::new(
+      to_unit_float(nested_uniform_scramble(sobol(index, 0), x_seed as u32)),
+      to_unit_float(nested_uniform_scramble(sobol(index, 1), y_seed as u32)),
+    )
+  }
+
+  fn sample_seed(&self) -> u64 {
+    sample_seed(self.seed, self.pixel, self.sample_index)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Draws `count` samples of a pixel, returning the 1D value and the following 2D value of each.
+  fn draw(sampler: &mut dyn Sampler, pixel: UVec2, count: u32) -> Vec<(f32, Vec2)> {
+    sampler.start_pixel(pixel);
+    (0..count)
+      .map(|i| {
+        sampler.start_sample(i);
+        let u = sampler.get_1d();
+        (u, sampler.get_2d())
+      })
+      .collect()
+  }
+
+  /// Asserts that every one of the `n` equal intervals of `[0, 1)` holds exactly one value.
+  fn assert_one_per_stratum(values: impl Iterator<Item = f32>, n: u32) {
+    let mut hits = vec![0; n as usize];
+    for value in values {
+      assert!((0.0..1.0).contains(&value), "{value} outside [0, 1)");
+      hits[(value * n as f32) as usize] += 1;
+    }
+    assert!(hits.iter().all(|&h| h == 1), "strata hits {hits:?}");
+  }
+
+  #[test]
+  fn stratified_sampler_uses_every_stratum() {
+    let mut sampler = StratifiedSampler::new(16, 7);
+    for pixel in [UVec2::new(0, 0), UVec2::new(13, 5)] {
+      let samples = draw(&mut sampler, pixel, 16);
+      assert_one_per_stratum(samples.iter().map(|s| s.0), 16);
+      // 2D requests stratify over a 4x4 grid
+      assert_one_per_stratum(
+        samples
+          .iter()
+          .map(|s| ((s.1.y * 4.0).floor() * 4.0 + (s.1.x * 4.0).floor()) / 16.0),
+        16,
+      );
+    }
+  }
+
+  #[test]
+  fn low_discrepancy_samplers_stratify_first_dimension() {
+    let samplers: [Box<dyn Sampler>; 2] = [
+      Box::new(HaltonSampler::new(3)),
+      Box::new(SobolSampler::new(3)),
+    ];
+    for mut sampler in samplers {
+      let samples = draw(sampler.as_mut(), UVec2::new(2, 9), 16);
+      assert_one_per_stratum(samples.iter().map(|s| s.0), 16);
+    }
+  }
+
+  #[test]
+  fn samplers_are_deterministic_per_seed() {
+    let kinds = [
+      SamplerKind::Independent,
+      SamplerKind::Stratified,
+      SamplerKind::Halton,
+      SamplerKind::Sobol,
+    ];
+    for kind in kinds {
+      let pixel = UVec2::new(4, 11);
+      let a = draw(kind.create(8, 1).as_mut(), pixel, 8);
+      let b = draw(kind.create(8, 1).as_mut(), pixel, 8);
+      let c = draw(kind.create(8, 2).as_mut(), pixel, 8);
+      assert_eq!(a, b, "{kind:?} differs between runs with the same seed");
+      assert_ne!(a, c, "{kind:?} ignores the seed");
+    }
   }
 
-  fn get_2d(&mut self) -> glam::Vec2 {
-    glam::Vec2::new(self.rng.gen(), self.rng.gen())
+  #[test]
+  fn samples_do_not_depend_on_visiting_order() {
+    // workers pick up tiles in any order, so a pixel's samples must not depend on the last one
+    let kind = SamplerKind::Sobol;
+    let mut fresh = kind.create(4, 5);
+    let mut reused = kind.create(4, 5);
+    draw(reused.as_mut(), UVec2::new(30, 1), 3);
+    let pixel = UVec2::new(6, 6);
+    assert_eq!(
+      draw(fresh.as_mut(), pixel, 4),
+      draw(reused.as_mut(), pixel, 4)
+    );
   }
 }
@@ -1,15 +1,27 @@
 use super::{
-  camera::{Camera, PinholeCamera},
+  camera::{
+    Camera, EquirectangularCamera, FisheyeCamera, OmniStereoCamera, OrthographicCamera,
+    PinholeCamera, RealisticCamera, ThinLensCamera,
+  },
+  environment::Sky,
+  light::Light,
+  material::Material,
+  medium::{HomogeneousMedium, Medium},
   shape::{Triangle, TriangleMesh},
+  transform::AnimatedTransform,
+  volume::GridMedium,
 };
 use crate::{core::Read, gfx::Transform, prefabs};
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
 pub(super) enum Primitive {
   Empty,
   Camera(Arc<dyn Camera>),
-  Sphere(glam::Vec3, f32),
-  TriangleMesh(Arc<TriangleMesh>),
+  /// Sphere of the given radius centered on the origin of its transform.
+  Sphere(f32, Arc<AnimatedTransform>, Arc<Material>),
+  /// Mesh with its points in object space.
+  TriangleMesh(Arc<TriangleMesh>, Arc<AnimatedTransform>, Arc<Material>),
+  Light(Light),
 }
 
 pub(super) struct Node {
@@ -19,8 +31,12 @@ pub(super) struct Node {
 
 pub struct SceneEngine {
   pub(super) root: Node,
-  pub(super) cameras: Vec<Arc<dyn Camera>>,
+  /// Every camera with its shutter open and close times.
+  pub(super) cameras: Vec<(Arc<dyn Camera>, (f32, f32))>,
   pub(super) active_cam: usize,
+  pub(super) sky: Sky,
+  /// Medium filling the scene outside of every closed mesh with a medium of its own.
+  pub(super) medium: Option<prefabs::Medium>,
 }
 impl SceneEngine {
   pub fn new() -> Self {
@@ -31,45 +47,103 @@ impl SceneEngine {
       },
       cameras: Vec::new(),
       active_cam: 0,
+      sky: Sky::default(),
+      medium: None,
     }
   }
+  pub fn sky(&self) -> &Sky {
+    &self.sky
+  }
+  pub fn set_sky(&mut self, sky: Sky) {
+    self.sky = sky;
+  }
+  pub fn medium(&self) -> Option<&prefabs::Medium> {
+    self.medium.as_ref()
+  }
+  /// Fill the scene with a medium, which only `IntegratorKind::VolumePath` renders.
+  pub fn set_medium(&mut self, medium: Option<prefabs::Medium>) {
+    self.medium = medium;
+  }
   pub fn translate(&mut self, scene: &crate::core::Scene) {
-    self.root = self.translate_node(&scene.root);
+    let mut meshes = HashMap::new();
+    self.root = self.translate_node(&scene.root, &mut meshes);
   }
-  fn translate_node(&mut self, node: &crate::core::Node) -> Node {
+  /// Translate `node` and its children. Meshes sharing their data are converted once, keyed by
+  /// the address of that data in `meshes`.
+  fn translate_node(
+    &mut self,
+    node: &crate::core::Node,
+    meshes: &mut HashMap<*const prefabs::MeshData, Arc<TriangleMesh>>,
+  ) -> Node {
     let prim = {
       if let Some(transform) = node.get_component::<Read<Transform>>() {
+        let motion = AnimatedTransform::new(*transform.affine(), *transform.end_affine());
         let transform = transform.affine().clone();
+        let medium = node.get_component::<Read<prefabs::Medium>>();
+        let mut material = match node.get_component::<Read<prefabs::Material>>() {
+          Some(material) => Material::from(&*material),
+          // a medium without a material is bounded by an invisible surface
+          None if medium.is_some() => Material::from(&prefabs::Material::interface()),
+          None => Material::default(),
+        };
+        material.interior =
+          medium.map(|medium| Arc::new(HomogeneousMedium::from(&*medium)) as Arc<dyn Medium>);
+        let material = Arc::new(material);
         if let Some(sphere) = node.get_component::<Read<prefabs::GeomSphere>>() {
-          Primitive::Sphere(transform.translation.into(), sphere.radius)
+          // the radius alone sets the size of a sphere, so drop any scale
+          let rigid = |affine: &glam::Affine3A| {
+            let (_, rotation, translation) = affine.to_scale_rotation_translation();
+            glam::Affine3A::from_rotation_translation(rotation, translation)
+          };
+          let motion = AnimatedTransform::new(
+            rigid(&motion.interpolate(0.0)),
+            rigid(&motion.interpolate(1.0)),
+          );
+          Primitive::Sphere(sphere.radius, Arc::new(motion), material)
         } else if let Some(mesh) = node.get_component::<Read<prefabs::Mesh>>() {
           let mesh_data = mesh
             .try_get_data()
             .expect("Mesh data should not be dropped");
-          let points = mesh_data.vertices.clone();
-          let normals = mesh_data.normals.clone();
-          let texcoords = mesh_data.uvs.clone();
-          let (indices, tri_count) = match &mesh_data.indices {
-            Some(indices) => (indices.clone(), (indices.len() / 3) as u32),
-            None => (
-              (0..points.len()).map(|x| x as u32).collect::<Vec<_>>(),
-              (points.len() / 3) as u32,
-            ),
-          };
-          let object_to_world = transform;
-          let world_to_object = object_to_world.inverse();
-          Primitive::TriangleMesh(Arc::new(TriangleMesh::new(
-            points,
-            normals,
-            texcoords,
-            indices,
-            tri_count,
-            object_to_world,
-            world_to_object,
-          )))
+          let tri_mesh = meshes
+            .entry(mesh_data as *const prefabs::MeshData)
+            .or_insert_with(|| {
+              let points = mesh_data.vertices.clone();
+              let normals = mesh_data.normals.clone();
+              let texcoords = mesh_data.uvs.clone();
+              let (indices, tri_count) = match &mesh_data.indices {
+                Some(indices) => (indices.clone(), (indices.len() / 3) as u32),
+                None => (
+                  (0..points.len()).map(|x| x as u32).collect::<Vec<_>>(),
+                  (points.len() / 3) as u32,
+                ),
+              };
+              Arc::new(TriangleMesh::new(
+                points, normals, texcoords, indices, tri_count,
+              ))
+            })
+            .clone();
+          Primitive::TriangleMesh(tri_mesh, Arc::new(motion), material)
+        } else if let Some(volume) = node.get_component::<Read<prefabs::Volume>>() {
+          // the box of the grid bounds the volume with an invisible surface
+          let motion = Arc::new(motion);
+          let mut material = Material::from(&prefabs::Material::interface());
+          material.interior = Some(Arc::new(GridMedium::new(&volume, motion.clone())));
+          let (min, max) = volume.density.bounds();
+          let cuboid = Arc::new(TriangleMesh::cuboid(min, max));
+          Primitive::TriangleMesh(cuboid, motion, Arc::new(material))
         } else if let Some(camera) = node.get_component::<Read<prefabs::Camera>>() {
           let (near, far) = camera.clipping_planes;
-          let camera = match camera.projection {
+          let camera_shutter = camera.shutter;
+          let camera: Arc<dyn Camera> = match camera.projection {
+            prefabs::Projection::Perspective {
+              field_of_view,
+              aspect,
+            } if camera.lens.aperture_radius > 0.0 => Arc::new(ThinLensCamera::new(
+              field_of_view,
+              aspect,
+              camera.lens,
+              transform,
+            )),
             prefabs::Projection::Perspective {
               field_of_view,
               aspect,
@@ -85,11 +159,57 @@ impl SceneEngine {
               bottom,
               left,
               right,
-            } => todo!(),
+            } => Arc::new(OrthographicCamera::new(
+              left, right, bottom, top, near, transform,
+            )),
+            prefabs::Projection::Equirectangular => Arc::new(EquirectangularCamera::new(transform)),
+            prefabs::Projection::Fisheye {
+              field_of_view,
+              aspect,
+              mapping,
+            } => Arc::new(FisheyeCamera::new(
+              field_of_view,
+              aspect,
+              mapping,
+              transform,
+            )),
+            prefabs::Projection::OmniStereo {
+              interpupillary_distance,
+            } => Arc::new(OmniStereoCamera::new(interpupillary_distance, transform)),
+            prefabs::Projection::Realistic {
+              ref prescription,
+              film_diagonal,
+              aspect,
+              focus_distance,
+            } => Arc::new(RealisticCamera::new(
+              prescription,
+              film_diagonal,
+              aspect,
+              focus_distance,
+              transform,
+            )),
           };
-          self.cameras.push(camera.clone());
+          self.cameras.push((camera.clone(), camera_shutter));
           self.active_cam = 0;
           Primitive::Camera(camera)
+        } else if let Some(light) = node.get_component::<Read<prefabs::PointLight>>() {
+          Primitive::Light(Light::Point {
+            position: transform.translation,
+            intensity: light.color * light.intensity,
+          })
+        } else if let Some(light) = node.get_component::<Read<prefabs::SpotLight>>() {
+          Primitive::Light(Light::Spot {
+            position: transform.translation,
+            direction: transform.transform_vector3a(-glam::Vec3A::Z).normalize(),
+            intensity: light.color * light.intensity,
+            cos_inner: light.inner_angle.cos(),
+            cos_outer: light.outer_angle.cos(),
+          })
+        } else if let Some(light) = node.get_component::<Read<prefabs::DirectionalLight>>() {
+          Primitive::Light(Light::Directional {
+            direction: transform.transform_vector3a(-glam::Vec3A::Z).normalize(),
+            radiance: light.color * light.intensity,
+          })
         } else {
           Primitive::Empty
         }
@@ -99,7 +219,7 @@ impl SceneEngine {
     };
     let mut children = Vec::new();
     for child in node.children() {
-      children.push(self.translate_node(&child));
+      children.push(self.translate_node(&child, meshes));
     }
     Node { prim, children }
   }
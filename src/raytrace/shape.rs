@@ -1,8 +1,9 @@
 use super::hit::Hit;
-use crate::math::{coordinate_system, Ray};
+use crate::math::{coordinate_system, uniform_sample_sphere, Ray};
 use bvh::aabb::{Bounded, AABB};
 use std::{f32::consts::PI, sync::Arc};
 
+#[derive(Clone)]
 pub(super) enum Shape {
   Sphere(Sphere),
   Triangle(Triangle),
@@ -20,8 +21,27 @@ impl Shape {
       Shape::Triangle(triangle) => triangle.intersect(ray, hit),
     }
   }
+  /// Surface area once placed in the world by `object_to_world`.
+  pub(super) fn area(&self, object_to_world: &glam::Affine3A) -> f32 {
+    match &self {
+      Shape::Sphere(sphere) => sphere.area(object_to_world),
+      Shape::Triangle(triangle) => triangle.area(object_to_world),
+    }
+  }
+  /// Uniformly sample a point on the surface by area. The hit is in object space, with its
+  /// geometric normal on the front side.
+  pub(super) fn sample(&self, u: &glam::Vec2) -> Hit<'_> {
+    let mut hit = match &self {
+      Shape::Sphere(sphere) => sphere.sample(u),
+      Shape::Triangle(triangle) => triangle.sample(u),
+    };
+    hit.shape = Some(self);
+    hit.front = true;
+    hit
+  }
 }
 
+#[derive(Clone)]
 pub struct Sphere {
   center: glam::Vec3,
   radius: f32,
@@ -31,6 +51,27 @@ impl Sphere {
   pub fn new(center: glam::Vec3, radius: f32) -> Self {
     Self { center, radius }
   }
+  fn area(&self, object_to_world: &glam::Affine3A) -> f32 {
+    // spheres are only ever placed with rigid transforms
+    let scale = object_to_world.matrix3.x_axis.length();
+    4.0 * PI * self.radius * self.radius * scale * scale
+  }
+  /// Texture coordinates of the point with outward normal `n`.
+  fn uv(n: glam::Vec3A) -> glam::Vec2 {
+    let theta = (-n.y).acos();
+    let phi = (-n.z).atan2(n.x) + PI;
+    glam::Vec2::new(phi / (2.0 * PI), theta / PI)
+  }
+  fn sample(&self, u: &glam::Vec2) -> Hit<'static> {
+    let n = uniform_sample_sphere(u);
+    Hit {
+      p: glam::Vec3A::from(self.center) + n * self.radius,
+      ng: n,
+      ns: n,
+      uv: Self::uv(n),
+      ..Default::default()
+    }
+  }
   fn intersect<'a>(&'a self, ray: &Ray, hit: &mut Hit<'a>) -> bool {
     let center = glam::Vec3A::from(self.center);
 
@@ -58,13 +99,13 @@ impl Sphere {
     hit.ng = (hit.p - center).normalize();
     hit.ns = hit.ng;
 
-    let theta = (-hit.ns.y).acos();
-    let phi = (-hit.ns.z).atan2(hit.ns.x) + PI;
-    hit.uv = glam::Vec2::new(phi / (2.0 * PI), theta / PI);
+    hit.uv = Self::uv(hit.ns);
 
+    // derivatives of the point with respect to uv, which shrink towards the poles
     let north = glam::Vec3A::Y;
-    hit.dpdu = north.cross(hit.ns).normalize();
-    hit.dpdv = hit.ns.cross(hit.dpdu);
+    let tangent = north.cross(hit.ns);
+    hit.dpdu = 2.0 * PI * self.radius * tangent;
+    hit.dpdv = PI * self.radius * hit.ns.cross(tangent.normalize());
     hit.front = hit.ng.dot(-ray.direction) > 0.0;
     true
   }
@@ -78,14 +119,14 @@ impl Bounded for Sphere {
   }
 }
 
+/// Triangles in object space. Every instance of the mesh shares one of these and places it in
+/// the world with its own transform.
 pub struct TriangleMesh {
   pub points: Vec<glam::Vec3>,
   pub normals: Vec<glam::Vec3>,
   pub texcoords: Option<Vec<glam::Vec2>>,
   pub indices: Vec<u32>,
   pub tri_count: u32,
-  object_to_world: glam::Affine3A,
-  world_to_object: glam::Affine3A,
 }
 impl TriangleMesh {
   pub fn new(
@@ -94,8 +135,6 @@ impl TriangleMesh {
     texcoords: Option<Vec<glam::Vec2>>,
     indices: Vec<u32>,
     tri_count: u32,
-    object_to_world: glam::Affine3A,
-    world_to_object: glam::Affine3A,
   ) -> Self {
     Self {
       points,
@@ -103,12 +142,39 @@ impl TriangleMesh {
       texcoords,
       indices,
       tri_count,
-      object_to_world,
-      world_to_object,
     }
   }
+  /// Axis aligned box between the corners `min` and `max`, with its faces pointing outwards.
+  pub fn cuboid(min: glam::Vec3, max: glam::Vec3) -> Self {
+    let mut points = Vec::with_capacity(24);
+    let mut normals = Vec::with_capacity(24);
+    let mut indices = Vec::with_capacity(36);
+    for axis in 0..3 {
+      for side in [-1.0f32, 1.0] {
+        let mut normal = glam::Vec3::ZERO;
+        normal[axis] = side;
+        // the two other axes, ordered so that the corners wind counterclockwise from outside
+        let (u, v) = if side > 0.0 {
+          ((axis + 1) % 3, (axis + 2) % 3)
+        } else {
+          ((axis + 2) % 3, (axis + 1) % 3)
+        };
+        let base = points.len() as u32;
+        for (a, b) in [(false, false), (true, false), (true, true), (false, true)] {
+          let mut p = if side > 0.0 { max } else { min };
+          p[u] = if a { max[u] } else { min[u] };
+          p[v] = if b { max[v] } else { min[v] };
+          points.push(p);
+          normals.push(normal);
+        }
+        indices.extend([base, base + 1, base + 2, base, base + 2, base + 3]);
+      }
+    }
+    Self::new(points, normals, None, indices, 12)
+  }
 }
 
+#[derive(Clone)]
 pub struct Triangle {
   mesh: Arc<TriangleMesh>,
   pub id: u32,
@@ -145,6 +211,29 @@ impl Triangle {
       self.mesh.normals[self.mesh.indices[(self.id * 3) as usize + 2] as usize],
     ]
   }
+  fn area(&self, object_to_world: &glam::Affine3A) -> f32 {
+    let [p0, p1, p2] = self
+      .points()
+      .map(|p| object_to_world.transform_point3a(p.into()));
+    (p1 - p0).cross(p2 - p0).length() * 0.5
+  }
+  fn sample(&self, u: &glam::Vec2) -> Hit<'static> {
+    let [p0, p1, p2] = self.points().map(glam::Vec3A::from);
+    let [n0, n1, n2] = self.normals().map(glam::Vec3A::from);
+    let [uv0, uv1, uv2] = self.uvs();
+    // uniform barycentric coordinates
+    let su0 = u.x.sqrt();
+    let b0 = 1.0 - su0;
+    let b1 = u.y * su0;
+    let b2 = 1.0 - b0 - b1;
+    Hit {
+      p: p0 * b0 + p1 * b1 + p2 * b2,
+      ng: (p1 - p0).cross(p2 - p0).normalize(),
+      ns: (n0 * b0 + n1 * b1 + n2 * b2).normalize(),
+      uv: uv0 * b0 + uv1 * b1 + uv2 * b2,
+      ..Default::default()
+    }
+  }
   fn intersect<'a>(&'a self, ray: &Ray, hit: &mut Hit<'a>) -> bool {
     let uvs = self.uvs();
     let [p0, p1, p2] = self.points().map(|p| glam::Vec3A::from(p));
@@ -214,7 +303,7 @@ impl Triangle {
 
     hit.p = p;
     hit.t = t.min(hit.t);
-    hit.ng = ng;
+    hit.ng = ng.normalize();
     hit.ns = (n0 * u + n1 * v + n2 * w).normalize();
     hit.front = hit.ng.dot(-ray.direction) > 0.0;
 
@@ -0,0 +1,194 @@
+use super::{hit::Hit, imageio::Image, transform::transform_normal};
+use crate::{math::Color, prefabs};
+use glam::{Mat2, Vec2};
+use std::sync::Arc;
+
+/// Spatially varying color, evaluated where a ray hits a surface.
+pub(super) trait Texture: Send + Sync {
+  fn evaluate(&self, hit: &Hit) -> Color;
+  /// The value of the texture when it is the same everywhere.
+  fn constant(&self) -> Option<Color> {
+    None
+  }
+}
+
+pub(super) struct ConstantTexture(Color);
+
+impl Texture for ConstantTexture {
+  fn evaluate(&self, _hit: &Hit) -> Color {
+    self.0
+  }
+  fn constant(&self) -> Option<Color> {
+    Some(self.0)
+  }
+}
+
+/// Texture defined over the 2D texture space, looked up through a `prefabs::TextureMapping`.
+trait Pattern: Send + Sync {
+  fn lookup(&self, uv: Vec2) -> Color;
+}
+
+struct Checkerboard {
+  even: Color,
+  odd: Color,
+}
+
+impl Pattern for Checkerboard {
+  fn lookup(&self, uv: Vec2) -> Color {
+    if (uv.x.floor() + uv.y.floor()) as i64 % 2 == 0 {
+      self.even
+    } else {
+      self.odd
+    }
+  }
+}
+
+struct UvDebug;
+
+impl Pattern for UvDebug {
+  fn lookup(&self, uv: Vec2) -> Color {
+    Color::new(uv.x - uv.x.floor(), uv.y - uv.y.floor(), 0.0)
+  }
+}
+
+struct Bitmap {
+  image: Arc<Image>,
+  wrap: prefabs::WrapMode,
+}
+
+impl Bitmap {
+  /// Bring the integer coordinate `i` into `[0, size)`.
+  fn wrap(&self, i: i64, size: u32) -> u32 {
+    let size = size as i64;
+    let i = match self.wrap {
+      prefabs::WrapMode::Repeat => i.rem_euclid(size),
+      prefabs::WrapMode::Clamp => i.clamp(0, size - 1),
+      prefabs::WrapMode::Mirror => {
+        let i = i.rem_euclid(2 * size);
+        if i < size {
+          i
+        } else {
+          2 * size - 1 - i
+        }
+      }
+    };
+    i as u32
+  }
+
+  fn texel(&self, x: i64, y: i64) -> Color {
+    let x = self.wrap(x, self.image.width());
+    let y = self.wrap(y, self.image.height());
+    self.image.pixel(x, y)
+  }
+}
+
+impl Pattern for Bitmap {
+  /// Bilinear interpolation between the four texels around `uv`, with texel centers at half
+  /// integer coordinates.
+  fn lookup(&self, uv: Vec2) -> Color {
+    let x = uv.x * self.image.width() as f32 - 0.5;
+    let y = uv.y * self.image.height() as f32 - 0.5;
+    let (x0, y0) = (x.floor(), y.floor());
+    let (dx, dy) = (x - x0, y - y0);
+    let (x0, y0) = (x0 as i64, y0 as i64);
+    self.texel(x0, y0) * ((1.0 - dx) * (1.0 - dy))
+      + self.texel(x0 + 1, y0) * (dx * (1.0 - dy))
+      + self.texel(x0, y0 + 1) * ((1.0 - dx) * dy)
+      + self.texel(x0 + 1, y0 + 1) * (dx * dy)
+  }
+}
+
+/// A pattern placed on the surface by a texture mapping.
+struct MappedTexture<P: Pattern> {
+  pattern: P,
+  mapping: prefabs::TextureMapping,
+}
+
+impl<P: Pattern> Texture for MappedTexture<P> {
+  fn evaluate(&self, hit: &Hit) -> Color {
+    match self.mapping {
+      prefabs::TextureMapping::Uv(transform) => {
+        let uv = Mat2::from_angle(transform.rotation) * (hit.uv * transform.scale);
+        self.pattern.lookup(uv + transform.offset)
+      }
+      prefabs::TextureMapping::Triplanar { scale, sharpness } => {
+        // project in object space, so that the texture sticks to moving objects
+        let p = hit.object_to_world.inverse().transform_point3a(hit.p) * scale;
+        let n = transform_normal(&hit.object_to_world, hit.ns);
+        let weights = n.abs().powf(sharpness);
+        let weights = weights / (weights.x + weights.y + weights.z);
+        let mut color = Color::BLACK;
+        for (weight, uv) in [
+          (weights.x, Vec2::new(p.z, p.y)),
+          (weights.y, Vec2::new(p.x, p.z)),
+          (weights.z, Vec2::new(p.x, p.y)),
+        ] {
+          if weight > 0.0 {
+            color = color + self.pattern.lookup(uv) * weight;
+          }
+        }
+        color
+      }
+    }
+  }
+}
+
+/// Convert a prefab texture.
+pub(super) fn create_texture(texture: &prefabs::Texture) -> Box<dyn Texture> {
+  match texture {
+    prefabs::Texture::Constant(color) => Box::new(ConstantTexture(*color)),
+    prefabs::Texture::Checkerboard { even, odd, mapping } => Box::new(MappedTexture {
+      pattern: Checkerboard {
+        even: *even,
+        odd: *odd,
+      },
+      mapping: *mapping,
+    }),
+    prefabs::Texture::UvDebug { mapping } => Box::new(MappedTexture {
+      pattern: UvDebug,
+      mapping: *mapping,
+    }),
+    prefabs::Texture::Image {
+      image,
+      wrap,
+      mapping,
+    } => Box::new(MappedTexture {
+      pattern: Bitmap {
+        image: image.clone(),
+        wrap: *wrap,
+      },
+      mapping: *mapping,
+    }),
+  }
+}
+
+/// Scalar texture reading one channel of a color texture.
+pub(super) struct FloatTexture {
+  texture: Box<dyn Texture>,
+  channel: prefabs::Channel,
+}
+
+impl FloatTexture {
+  fn channel(&self, color: Color) -> f32 {
+    match self.channel {
+      prefabs::Channel::Red => color.r,
+      prefabs::Channel::Green => color.g,
+      prefabs::Channel::Blue => color.b,
+    }
+  }
+  pub fn evaluate(&self, hit: &Hit) -> f32 {
+    self.channel(self.texture.evaluate(hit))
+  }
+  pub fn constant(&self) -> Option<f32> {
+    self.texture.constant().map(|color| self.channel(color))
+  }
+}
+
+impl From<&prefabs::FloatTexture> for FloatTexture {
+  fn from(texture: &prefabs::FloatTexture) -> Self {
+    Self {
+      texture: create_texture(&texture.texture),
+      channel: texture.channel,
+    }
+  }
+}
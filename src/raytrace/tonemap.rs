@@ -0,0 +1,118 @@
+use crate::math::Color;
+
+/// Operators compressing scene-referred radiance into the displayable $[0,1]$ range.
+#[derive(Clone, Copy, Debug)]
+pub enum ToneMapping {
+  Clamp,
+  /// Reinhard et al. 2002, applied to luminance.
+  Reinhard,
+  /// Reinhard with the given luminance mapped to pure white.
+  ExtendedReinhard {
+    white_point: f32,
+  },
+  /// Stephen Hill's fit of the ACES reference rendering and output transforms.
+  AcesFitted,
+  /// John Hable's filmic curve from Uncharted 2.
+  Hable,
+}
+
+impl ToneMapping {
+  pub fn apply(&self, color: Color) -> Color {
+    match self {
+      ToneMapping::Clamp => color,
+      ToneMapping::Reinhard => scale_luminance(color, |l| l / (1.0 + l)),
+      ToneMapping::ExtendedReinhard { white_point } => {
+        let white_sq = white_point * white_point;
+        scale_luminance(color, |l| l * (1.0 + l / white_sq) / (1.0 + l))
+      }
+      ToneMapping::AcesFitted => aces_fitted(color),
+      ToneMapping::Hable => {
+        const WHITE_POINT: f32 = 11.2;
+        const EXPOSURE_BIAS: f32 = 2.0;
+        let white_scale = 1.0 / hable_partial(WHITE_POINT);
+        Color::new(
+          hable_partial(color.r * EXPOSURE_BIAS) * white_scale,
+          hable_partial(color.g * EXPOSURE_BIAS) * white_scale,
+          hable_partial(color.b * EXPOSURE_BIAS) * white_scale,
+        )
+      }
+    }
+  }
+}
+
+fn scale_luminance<F: Fn(f32) -> f32>(color: Color, f: F) -> Color {
+  let l = color.luminance();
+  if l <= 0.0 {
+    Color::BLACK
+  } else {
+    color * (f(l) / l)
+  }
+}
+
+fn aces_fitted(color: Color) -> Color {
+  // sRGB => XYZ => D65_2_D60 => AP1 => RRT_SAT
+  let input = [
+    [0.59719, 0.35458, 0.04823],
+    [0.07600, 0.90834, 0.01566],
+    [0.02840, 0.13383, 0.83777],
+  ];
+  // ODT_SAT => XYZ => D60_2_D65 => sRGB
+  let output = [
+    [1.60475, -0.53108, -0.07367],
+    [-0.10208, 1.10813, -0.00605],
+    [-0.00327, -0.07276, 1.07602],
+  ];
+  let mul = |m: &[[f32; 3]; 3], c: Color| {
+    Color::new(
+      m[0][0] * c.r + m[0][1] * c.g + m[0][2] * c.b,
+      m[1][0] * c.r + m[1][1] * c.g + m[1][2] * c.b,
+      m[2][0] * c.r + m[2][1] * c.g + m[2][2] * c.b,
+    )
+  };
+  let rrt_and_odt_fit = |v: f32| {
+    let a = v * (v + 0.0245786) - 0.000090537;
+    let b = v * (0.983729 * v + 0.432951) + 0.238081;
+    a / b
+  };
+  let c = mul(&input, color);
+  let c = Color::new(
+    rrt_and_odt_fit(c.r),
+    rrt_and_odt_fit(c.g),
+    rrt_and_odt_fit(c.b),
+  );
+  mul(&output, c)
+}
+
+fn hable_partial(x: f32) -> f32 {
+  const A: f32 = 0.15;
+  const B: f32 = 0.50;
+  const C: f32 = 0.10;
+  const D: f32 = 0.20;
+  const E: f32 = 0.02;
+  const F: f32 = 0.30;
+  ((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F
+}
+
+/// How the film's linear radiance is turned into 8-bit sRGB display values.
+#[derive(Clone, Copy, Debug)]
+pub struct DisplaySettings {
+  /// Exposure adjustment in stops (EV).
+  pub exposure: f32,
+  pub tone_mapping: ToneMapping,
+}
+
+impl Default for DisplaySettings {
+  fn default() -> Self {
+    Self {
+      exposure: 0.0,
+      tone_mapping: ToneMapping::Clamp,
+    }
+  }
+}
+
+impl DisplaySettings {
+  pub fn apply(&self, color: Color) -> [u8; 4] {
+    let exposed = color * 2f32.powf(self.exposure);
+    self.tone_mapping.apply(exposed).to_srgb().into()
+  }
+}
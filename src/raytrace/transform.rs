@@ -0,0 +1,92 @@
+use bvh::aabb::AABB;
+use glam::{Affine3A, Quat, Vec3, Vec3A};
+
+/// Number of steps used to bound the region swept by an animated transform.
+const MOTION_BOUND_STEPS: usize = 64;
+
+/// Object to world transform interpolated between a keyframe at time 0 and one at time 1.
+/// Scale and translation are interpolated linearly and rotation spherically.
+pub(super) struct AnimatedTransform {
+  start: Affine3A,
+  start_inverse: Affine3A,
+  end: Affine3A,
+  start_parts: (Vec3, Quat, Vec3),
+  end_parts: (Vec3, Quat, Vec3),
+  animated: bool,
+}
+
+impl AnimatedTransform {
+  pub fn new(start: Affine3A, end: Affine3A) -> Self {
+    Self {
+      start,
+      start_inverse: start.inverse(),
+      end,
+      start_parts: start.to_scale_rotation_translation(),
+      end_parts: end.to_scale_rotation_translation(),
+      animated: start != end,
+    }
+  }
+
+  /// The transform at `time`, clamped to the keyframes.
+  pub fn interpolate(&self, time: f32) -> Affine3A {
+    if !self.animated || time <= 0.0 {
+      return self.start;
+    }
+    if time >= 1.0 {
+      return self.end;
+    }
+    let (s0, r0, t0) = self.start_parts;
+    let (s1, r1, t1) = self.end_parts;
+    Affine3A::from_scale_rotation_translation(
+      s0.lerp(s1, time),
+      r0.slerp(r1, time),
+      t0.lerp(t1, time),
+    )
+  }
+
+  /// The transform at `time` together with its inverse.
+  pub fn interpolate_with_inverse(&self, time: f32) -> (Affine3A, Affine3A) {
+    if !self.animated {
+      return (self.start, self.start_inverse);
+    }
+    let transform = self.interpolate(time);
+    (transform, transform.inverse())
+  }
+
+  /// World space box containing `bound` at every time within the keyframes.
+  pub fn motion_bounds(&self, bound: &AABB) -> AABB {
+    if !self.animated {
+      return transform_aabb(&self.start, bound);
+    }
+    let mut result = AABB::empty();
+    for step in 0..=MOTION_BOUND_STEPS {
+      let time = step as f32 / MOTION_BOUND_STEPS as f32;
+      result.join_mut(&transform_aabb(&self.interpolate(time), bound));
+    }
+    // rotation may bulge slightly past the sampled boxes between steps
+    let padding = result.size() * (0.5 / MOTION_BOUND_STEPS as f32);
+    AABB::with_bounds(result.min - padding, result.max + padding)
+  }
+}
+
+pub(super) fn transform_aabb(transform: &Affine3A, bound: &AABB) -> AABB {
+  let mut result = AABB::empty();
+  if bound.is_empty() {
+    return result;
+  }
+  for i in 0..8 {
+    let corner = Vec3A::new(
+      if i & 1 == 0 { bound.min.x } else { bound.max.x },
+      if i & 2 == 0 { bound.min.y } else { bound.max.y },
+      if i & 4 == 0 { bound.min.z } else { bound.max.z },
+    );
+    let p = transform.transform_point3a(corner);
+    result.grow_mut(&bvh::Point3::new(p.x, p.y, p.z));
+  }
+  result
+}
+
+/// Transform a surface normal, which uses the inverse transpose of the linear part.
+pub(super) fn transform_normal(inverse: &Affine3A, n: Vec3A) -> Vec3A {
+  inverse.matrix3.transpose().mul_vec3a(n).normalize()
+}
@@ -0,0 +1,513 @@
+use super::{
+  medium::{HenyeyGreenstein, Medium, MediumInteraction, MediumSample},
+  rng::Pcg32,
+  transform::AnimatedTransform,
+};
+use crate::{
+  math::{Color, Ray},
+  prefabs,
+};
+use glam::{UVec3, Vec3, Vec3A};
+use std::{
+  fs,
+  io::{BufReader, BufWriter, Read, Write},
+  path::Path,
+  sync::Arc,
+};
+
+#[derive(Debug)]
+pub enum VolumeError {
+  Io(std::io::Error),
+  InvalidData(String),
+}
+
+impl From<std::io::Error> for VolumeError {
+  fn from(e: std::io::Error) -> Self {
+    VolumeError::Io(e)
+  }
+}
+
+/// Dense grid of voxel values over an axis aligned box, stored in the `.vol` format of Mitsuba.
+/// The header holds the bytes `VOL` and version 3, then as little endian 32-bit values the
+/// encoding (1 for floats), the resolution along x, y and z, the channel count and the box,
+/// minimum corner first. The floats follow with channels interleaved and x varying fastest,
+/// then y, then z. Values are interpolated trilinearly between voxel centers.
+pub struct VoxelGrid {
+  resolution: UVec3,
+  channels: u32,
+  min: Vec3A,
+  max: Vec3A,
+  data: Vec<f32>,
+}
+
+/// Number of values in a grid, or `None` if it does not fit in memory.
+fn value_count(resolution: UVec3, channels: u32) -> Option<usize> {
+  (resolution.x as usize)
+    .checked_mul(resolution.y as usize)?
+    .checked_mul(resolution.z as usize)?
+    .checked_mul(channels as usize)
+}
+
+impl VoxelGrid {
+  pub fn new(resolution: UVec3, channels: u32, min: Vec3, max: Vec3, data: Vec<f32>) -> Self {
+    assert!(resolution.min_element() > 0 && channels > 0);
+    assert!(min.cmplt(max).all(), "empty box");
+    assert_eq!(Some(data.len()), value_count(resolution, channels));
+    Self {
+      resolution,
+      channels,
+      min: min.into(),
+      max: max.into(),
+      data,
+    }
+  }
+
+  pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, VolumeError> {
+    let file = fs::File::open(path)?;
+    let file_size = file.metadata()?.len();
+    let mut reader = BufReader::new(file);
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != b"VOL\x03" {
+      return Err(VolumeError::InvalidData("not a version 3 volume".into()));
+    }
+    let mut header = [0; 44];
+    reader.read_exact(&mut header)?;
+    let word = |i: usize| -> [u8; 4] { header[4 * i..4 * i + 4].try_into().unwrap() };
+    let int = |i: usize| i32::from_le_bytes(word(i));
+    let float = |i: usize| f32::from_le_bytes(word(i));
+    if int(0) != 1 {
+      return Err(VolumeError::InvalidData(format!(
+        "unsupported encoding {}",
+        int(0)
+      )));
+    }
+    let (x, y, z, channels) = (int(1), int(2), int(3), int(4));
+    if x <= 0 || y <= 0 || z <= 0 || channels <= 0 {
+      return Err(VolumeError::InvalidData("empty volume".into()));
+    }
+    let resolution = UVec3::new(x as u32, y as u32, z as u32);
+    let min = Vec3::new(float(5), float(6), float(7));
+    let max = Vec3::new(float(8), float(9), float(10));
+    if !min.cmplt(max).all() {
+      return Err(VolumeError::InvalidData("empty box".into()));
+    }
+    // check the size against the file before allocating for it
+    let size = value_count(resolution, channels as u32)
+      .and_then(|count| count.checked_mul(4))
+      .filter(|&size| size as u64 <= file_size - 48)
+      .ok_or_else(|| VolumeError::InvalidData("volume larger than the file".into()))?;
+    let mut bytes = vec![0; size];
+    reader.read_exact(&mut bytes)?;
+    let data = bytes
+      .chunks_exact(4)
+      .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+      .collect();
+    Ok(Self::new(resolution, channels as u32, min, max, data))
+  }
+
+  pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), VolumeError> {
+    let mut out = BufWriter::new(fs::File::create(path)?);
+    out.write_all(b"VOL\x03")?;
+    let ints = [
+      1,
+      self.resolution.x as i32,
+      self.resolution.y as i32,
+      self.resolution.z as i32,
+      self.channels as i32,
+    ];
+    for v in ints {
+      out.write_all(&v.to_le_bytes())?;
+    }
+    for v in self.min.to_array().into_iter().chain(self.max.to_array()) {
+      out.write_all(&v.to_le_bytes())?;
+    }
+    for v in &self.data {
+      out.write_all(&v.to_le_bytes())?;
+    }
+    Ok(())
+  }
+
+  pub fn resolution(&self) -> UVec3 {
+    self.resolution
+  }
+
+  pub fn channels(&self) -> u32 {
+    self.channels
+  }
+
+  /// Minimum and maximum corners of the box covered by the grid.
+  pub fn bounds(&self) -> (Vec3, Vec3) {
+    (self.min.into(), self.max.into())
+  }
+
+  /// Value of a voxel, with indices clamped to the grid.
+  fn voxel(&self, x: i32, y: i32, z: i32, channel: u32) -> f32 {
+    let r = self.resolution.as_ivec3();
+    let (x, y, z) = (
+      x.clamp(0, r.x - 1) as usize,
+      y.clamp(0, r.y - 1) as usize,
+      z.clamp(0, r.z - 1) as usize,
+    );
+    let index = (z * self.resolution.y as usize + y) * self.resolution.x as usize + x;
+    self.data[index * self.channels as usize + channel as usize]
+  }
+
+  /// Interpolated value of `channel` at the point `p`, or zero outside of the grid.
+  pub(super) fn lookup(&self, p: Vec3A, channel: u32) -> f32 {
+    if p.cmplt(self.min).any() || p.cmpgt(self.max).any() {
+      return 0.0;
+    }
+    let g = (p - self.min) / (self.max - self.min) * self.resolution.as_vec3a() - 0.5;
+    let base = g.floor();
+    let d = g - base;
+    let (x, y, z) = (base.x as i32, base.y as i32, base.z as i32);
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+    let row = |y: i32, z: i32| {
+      lerp(
+        self.voxel(x, y, z, channel),
+        self.voxel(x + 1, y, z, channel),
+        d.x,
+      )
+    };
+    let plane = |z: i32| lerp(row(y, z), row(y + 1, z), d.y);
+    lerp(plane(z), plane(z + 1), d.z)
+  }
+
+  /// Interpolated color at `p`, gray for grids with fewer than three channels.
+  fn lookup_color(&self, p: Vec3A) -> Color {
+    if self.channels >= 3 {
+      Color::new(self.lookup(p, 0), self.lookup(p, 1), self.lookup(p, 2))
+    } else {
+      Color::splat(self.lookup(p, 0))
+    }
+  }
+}
+
+/// Voxels per axis in each cell of the majorant grid.
+const MAJORANT_CELL: u32 = 8;
+
+/// Coarse grid over the same box as a density grid, holding the largest density that
+/// interpolation can reach in each of its cells.
+struct MajorantGrid {
+  resolution: UVec3,
+  values: Vec<f32>,
+}
+
+impl MajorantGrid {
+  fn new(grid: &VoxelGrid) -> Self {
+    let resolution = (grid.resolution + MAJORANT_CELL - 1) / MAJORANT_CELL;
+    let mut values = Vec::with_capacity(value_count(resolution, 1).unwrap());
+    for cz in 0..resolution.z as i32 {
+      for cy in 0..resolution.y as i32 {
+        for cx in 0..resolution.x as i32 {
+          // interpolation inside a cell also reads the voxels just around it
+          let cell = MAJORANT_CELL as i32;
+          let mut max = 0.0f32;
+          for z in cz * cell - 1..=(cz + 1) * cell {
+            for y in cy * cell - 1..=(cy + 1) * cell {
+              for x in cx * cell - 1..=(cx + 1) * cell {
+                max = max.max(grid.voxel(x, y, z, 0));
+              }
+            }
+          }
+          values.push(max);
+        }
+      }
+    }
+    Self { resolution, values }
+  }
+
+  /// Scale from offsets in the box of `grid` to cell units. Cells line up with the blocks of
+  /// voxels they bound, so the last ones may reach past the box.
+  fn scale(grid: &VoxelGrid) -> Vec3A {
+    grid.resolution.as_vec3a() / (grid.max - grid.min) / MAJORANT_CELL as f32
+  }
+
+  fn value(&self, cell: glam::IVec3) -> f32 {
+    let (x, y, z) = (cell.x as usize, cell.y as usize, cell.z as usize);
+    self.values[(z * self.resolution.y as usize + y) * self.resolution.x as usize + x]
+  }
+}
+
+/// Normalized emission of a black body at `temperature` kelvin, sampled at red, green and blue
+/// wavelengths and divided by its peak.
+fn blackbody(temperature: f32) -> Color {
+  if temperature <= 0.0 {
+    return Color::BLACK;
+  }
+  const C: f64 = 299792458.0;
+  const H: f64 = 6.62606957e-34;
+  const KB: f64 = 1.3806488e-23;
+  let temperature = temperature as f64;
+  let planck = |lambda: f64| {
+    2.0 * H * C * C / (lambda.powi(5) * ((H * C / (lambda * KB * temperature)).exp() - 1.0))
+  };
+  // Wien's displacement law gives the wavelength of the peak
+  let peak = planck(2.8977721e-3 / temperature);
+  let channel = |nanometers: f64| (planck(nanometers * 1e-9) / peak) as f32;
+  Color::new(channel(610.0), channel(550.0), channel(465.0))
+}
+
+/// Heterogeneous medium given by voxel grids, rendered with delta tracking for scattering and
+/// ratio tracking for transmittance. Both walk the cells of a majorant grid along the ray.
+pub(super) struct GridMedium {
+  density: Arc<VoxelGrid>,
+  emission: Option<Arc<VoxelGrid>>,
+  temperature: Option<Arc<VoxelGrid>>,
+  emission_scale: f32,
+  sigma_t: f32,
+  albedo: Color,
+  phase: HenyeyGreenstein,
+  majorants: MajorantGrid,
+  transform: Arc<AnimatedTransform>,
+}
+
+impl GridMedium {
+  pub fn new(volume: &prefabs::Volume, transform: Arc<AnimatedTransform>) -> Self {
+    Self {
+      density: volume.density.clone(),
+      emission: volume.emission.clone(),
+      temperature: volume.temperature.clone(),
+      emission_scale: volume.emission_scale,
+      sigma_t: volume.sigma_t,
+      albedo: volume.albedo,
+      phase: HenyeyGreenstein::new(volume.g),
+      majorants: MajorantGrid::new(&volume.density),
+      transform,
+    }
+  }
+
+  /// Emitted radiance at the object space point `p`.
+  fn emitted(&self, p: Vec3A) -> Color {
+    let mut le = Color::BLACK;
+    if let Some(emission) = &self.emission {
+      le = le + emission.lookup_color(p);
+    }
+    if let Some(temperature) = &self.temperature {
+      le = le + blackbody(temperature.lookup(p, 0));
+    }
+    le * self.emission_scale
+  }
+
+  /// Walk the tentative collisions along `ray` before `t_max`, sampled with the majorant of
+  /// every cell the ray crosses. `collision` receives each with its object space position, its
+  /// distance along the ray and the majorant, and returns whether to keep walking.
+  fn track(
+    &self,
+    ray: &Ray,
+    t_max: f32,
+    rng: &mut Pcg32,
+    mut collision: impl FnMut(&mut Pcg32, Vec3A, f32, f32) -> bool,
+  ) {
+    let (_, world_to_object) = self.transform.interpolate_with_inverse(ray.time);
+    let origin = world_to_object.transform_point3a(ray.origin);
+    let direction = world_to_object.transform_vector3a(ray.direction);
+    let length = ray.direction.length();
+
+    // clip the ray to the box of the grid
+    let inv = direction.recip();
+    let t0 = (self.density.min - origin) * inv;
+    let t1 = (self.density.max - origin) * inv;
+    let t_enter = t0.min(t1).max_element().max(0.0);
+    let t_exit = t0.max(t1).min_element().min(t_max);
+    if t_enter >= t_exit {
+      return;
+    }
+
+    // walk the majorant cells with a 3D digital differential analyzer, in cell units
+    let scale = MajorantGrid::scale(&self.density);
+    let p = (origin + t_enter * direction - self.density.min) * scale;
+    let d = direction * scale;
+    let last = self.majorants.resolution.as_ivec3() - 1;
+    let mut cell = p.floor().as_ivec3().clamp(glam::IVec3::ZERO, last);
+    let mut step = glam::IVec3::ZERO;
+    let mut next = Vec3A::splat(f32::INFINITY);
+    let mut delta = Vec3A::splat(f32::INFINITY);
+    for axis in 0..3 {
+      if d[axis] > 0.0 {
+        step[axis] = 1;
+        next[axis] = t_enter + (cell[axis] as f32 + 1.0 - p[axis]) / d[axis];
+        delta[axis] = 1.0 / d[axis];
+      } else if d[axis] < 0.0 {
+        step[axis] = -1;
+        next[axis] = t_enter + (cell[axis] as f32 - p[axis]) / d[axis];
+        delta[axis] = -1.0 / d[axis];
+      }
+    }
+
+    let mut t = t_enter;
+    loop {
+      let axis = if next.x <= next.y && next.x <= next.z {
+        0
+      } else if next.y <= next.z {
+        1
+      } else {
+        2
+      };
+      let t_end = next[axis].min(t_exit);
+      let sigma_maj = self.sigma_t * self.majorants.value(cell);
+      if sigma_maj > 0.0 {
+        // the exponential distribution restarts at every cell boundary without bias
+        loop {
+          t -= (1.0 - rng.uniform_f32()).ln() / (sigma_maj * length);
+          if t >= t_end {
+            break;
+          }
+          if !collision(rng, origin + t * direction, t, sigma_maj) {
+            return;
+          }
+        }
+      }
+      if t_end >= t_exit {
+        return;
+      }
+      t = t_end;
+      cell[axis] += step[axis];
+      if cell[axis] < 0 || cell[axis] > last[axis] {
+        return;
+      }
+      next[axis] += delta[axis];
+    }
+  }
+}
+
+impl Medium for GridMedium {
+  fn tr(&self, ray: &Ray, t_max: f32, rng: &mut Pcg32) -> Color {
+    let mut tr = 1.0;
+    self.track(ray, t_max, rng, |rng, p, _, sigma_maj| {
+      tr *= 1.0 - self.sigma_t * self.density.lookup(p, 0) / sigma_maj;
+      // Russian roulette once little light gets through
+      if tr < 0.1 {
+        if rng.uniform_f32() < 0.75 {
+          tr = 0.0;
+          return false;
+        }
+        tr /= 0.25;
+      }
+      true
+    });
+    Color::splat(tr)
+  }
+
+  fn sample(&self, ray: &Ray, t_max: f32, rng: &mut Pcg32) -> MediumSample {
+    let absorption = Color::WHITE - self.albedo;
+    let emits = self.emission.is_some() || self.temperature.is_some();
+    let mut emission = Color::BLACK;
+    let mut scattered = None;
+    self.track(ray, t_max, rng, |rng, p, t, sigma_maj| {
+      let sigma_t = self.sigma_t * self.density.lookup(p, 0);
+      // every tentative collision estimates the emission of the absorbing part
+      if emits {
+        emission = emission + absorption * self.emitted(p) * (sigma_t / sigma_maj);
+      }
+      if rng.uniform_f32() < sigma_t / sigma_maj {
+        scattered = Some(t);
+        return false;
+      }
+      true
+    });
+    match scattered {
+      Some(t) => MediumSample {
+        weight: self.albedo,
+        emission,
+        interaction: Some(MediumInteraction {
+          p: ray.origin + t * ray.direction,
+          phase: self.phase,
+        }),
+      },
+      None => MediumSample {
+        weight: Color::WHITE,
+        emission,
+        interaction: None,
+      },
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Grid over `[-1, 1]^3`, empty but for a few random voxels, so that majorants differ
+  /// between cells.
+  fn sparse_grid(resolution: UVec3, channels: u32) -> VoxelGrid {
+    let mut rng = Pcg32::default();
+    let count = (resolution.x * resolution.y * resolution.z * channels) as usize;
+    let data = (0..count)
+      .map(|_| {
+        if rng.uniform_f32() < 0.02 {
+          4.0 * rng.uniform_f32()
+        } else {
+          0.0
+        }
+      })
+      .collect();
+    VoxelGrid::new(resolution, channels, Vec3::splat(-1.0), Vec3::ONE, data)
+  }
+
+  #[test]
+  fn vol_file_round_trip() {
+    let grid = sparse_grid(UVec3::new(5, 3, 4), 3);
+    let path = std::env::temp_dir().join(format!("flux-round-trip-{}.vol", std::process::id()));
+    grid.save(&path).unwrap();
+    let loaded = VoxelGrid::load(&path);
+    fs::remove_file(&path).unwrap();
+    let loaded = loaded.unwrap();
+    assert_eq!(loaded.resolution(), grid.resolution());
+    assert_eq!(loaded.channels(), grid.channels());
+    assert_eq!(loaded.bounds(), grid.bounds());
+    assert_eq!(loaded.data, grid.data);
+  }
+
+  #[test]
+  fn corrupt_headers_are_rejected() {
+    let header = |resolution: i32, max: f32| {
+      let mut bytes = b"VOL\x03".to_vec();
+      for v in [1, resolution, resolution, resolution, 1] {
+        bytes.extend(i32::to_le_bytes(v));
+      }
+      for v in [0.0, 0.0, 0.0, max, max, max] {
+        bytes.extend(f32::to_le_bytes(v));
+      }
+      bytes.extend(f32::to_le_bytes(1.0));
+      bytes
+    };
+    let path = std::env::temp_dir().join(format!("flux-corrupt-{}.vol", std::process::id()));
+    for (bytes, valid) in [
+      (header(1, 1.0), true),
+      (header(i32::MAX, 1.0), false),
+      (header(2, 1.0), false),
+      (header(1, 0.0), false),
+    ] {
+      fs::write(&path, bytes).unwrap();
+      match VoxelGrid::load(&path) {
+        Ok(_) => assert!(valid),
+        Err(VolumeError::InvalidData(_)) => assert!(!valid),
+        Err(e) => panic!("{e:?}"),
+      }
+    }
+    fs::remove_file(&path).unwrap();
+  }
+
+  #[test]
+  fn majorants_bound_the_density() {
+    let mut rng = Pcg32::default();
+    // resolutions that are and are not multiples of the majorant cell size
+    for resolution in [UVec3::new(16, 8, 24), UVec3::new(13, 5, 19)] {
+      let grid = sparse_grid(resolution, 1);
+      let majorants = MajorantGrid::new(&grid);
+      let scale = MajorantGrid::scale(&grid);
+      let last = majorants.resolution.as_ivec3() - 1;
+      for _ in 0..100_000 {
+        let u = Vec3A::new(rng.uniform_f32(), rng.uniform_f32(), rng.uniform_f32());
+        let p = grid.min + u * (grid.max - grid.min);
+        let cell = ((p - grid.min) * scale)
+          .floor()
+          .as_ivec3()
+          .clamp(glam::IVec3::ZERO, last);
+        assert!(grid.lookup(p, 0) <= majorants.value(cell), "{p} in {cell}");
+      }
+    }
+  }
+}